@@ -0,0 +1,130 @@
+// ##### Agenda #####
+
+// These are the functions that pick out
+// what is due soon across every project,
+// for the Today and Upcoming views and
+// their subcommands. Only the due date
+// is looked at, a due time just decides
+// the order within a day.
+
+use chrono::{Local, NaiveDate};
+
+use crate::model::Task;
+
+/// How many days Upcoming covers unless asked otherwise, today included
+pub const UPCOMING_DAYS: i64 = 7;
+
+/// The longer Upcoming span the TUI can switch to
+pub const UPCOMING_DAYS_LONG: i64 = 14;
+
+/// The local date, which Todoist due dates are written in
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// A task's due date, if it has one Todoist has understood yet
+pub fn due_date(task: &Task) -> Option<NaiveDate> {
+    let due = task.due.as_ref()?;
+    NaiveDate::parse_from_str(&due.date, "%Y-%m-%d").ok()
+}
+
+/// Tasks that are overdue or due `today`, oldest first
+pub fn due_today(tasks: &[Task], today: NaiveDate) -> Vec<Task> {
+    let mut due: Vec<Task> = tasks.iter()
+        .filter(|task| due_date(task).is_some_and(|date| date <= today))
+        .cloned()
+        .collect();
+    sort_by_due(&mut due);
+    due
+}
+
+/// Tasks due in the `days` days from `today` on, grouped by date
+pub fn upcoming(tasks: &[Task], today: NaiveDate, days: i64) -> Vec<(NaiveDate, Vec<Task>)> {
+    let mut due: Vec<Task> = tasks.iter()
+        .filter(|task| due_date(task).is_some_and(|date| (0..days).contains(&(date - today).num_days())))
+        .cloned()
+        .collect();
+    sort_by_due(&mut due);
+
+    let mut grouped: Vec<(NaiveDate, Vec<Task>)> = Vec::new();
+    for task in due.into_iter() {
+        let date = due_date(&task).expect("Only tasks with a due date are kept");
+        match grouped.last_mut() {
+            Some((day, tasks)) if *day == date => tasks.push(task),
+            _ => grouped.push((date, vec![task])),
+        }
+    }
+    grouped
+}
+
+/// By due date, then time of day, then the most urgent first
+fn sort_by_due(tasks: &mut [Task]) {
+    tasks.sort_by(|a, b| {
+        let time = |task: &Task| task.due.as_ref().and_then(|due| due.datetime.clone());
+        due_date(a).cmp(&due_date(b))
+            .then_with(|| time(a).cmp(&time(b)))
+            .then_with(|| b.priority.cmp(&a.priority))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{date, due, due_at, priority, task};
+
+    fn ids(tasks: &[Task]) -> Vec<i64> {
+        tasks.iter().map(|task| task.id).collect()
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            due(task(1, 1, "Long overdue"), "2026-10-01"),
+            due(task(2, 1, "Today"), "2026-10-18"),
+            due(task(3, 1, "Tomorrow"), "2026-10-19"),
+            due(task(4, 1, "Last day of the week"), "2026-10-24"),
+            due(task(5, 1, "Just past the week"), "2026-10-25"),
+            task(6, 1, "Whenever"),
+        ]
+    }
+
+    #[test]
+    fn today_takes_in_everything_overdue() {
+        assert_eq!(ids(&due_today(&tasks(), date("2026-10-18"))), vec![1, 2]);
+    }
+
+    #[test]
+    fn upcoming_runs_from_today_for_the_given_days() {
+        let week = upcoming(&tasks(), date("2026-10-18"), UPCOMING_DAYS);
+        let days: Vec<NaiveDate> = week.iter().map(|(day, _)| *day).collect();
+
+        assert_eq!(days, vec![date("2026-10-18"), date("2026-10-19"), date("2026-10-24")]);
+        assert_eq!(ids(&week[0].1), vec![2]);
+        assert!(upcoming(&tasks(), date("2026-10-18"), 0).is_empty());
+    }
+
+    #[test]
+    fn due_time_orders_within_a_day_without_moving_it() {
+        let tasks = vec![
+            due_at(task(1, 1, "Evening"), "2026-10-18", "2026-10-18T19:00:00"),
+            due_at(task(2, 1, "Morning"), "2026-10-18", "2026-10-18T08:00:00Z"),
+            priority(due(task(3, 1, "Any time, urgent"), "2026-10-18"), 4),
+            due(task(4, 1, "Any time"), "2026-10-18"),
+            due_at(task(5, 1, "Late tonight"), "2026-10-18", "2026-10-18T23:30:00"),
+        ];
+
+        assert_eq!(ids(&due_today(&tasks, date("2026-10-18"))), vec![3, 4, 2, 1, 5]);
+        let upcoming = upcoming(&tasks, date("2026-10-18"), 1);
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(ids(&upcoming[0].1), vec![3, 4, 2, 1, 5]);
+    }
+
+    #[test]
+    fn unparsed_due_dates_are_left_out() {
+        let mut pending = due(task(1, 1, "Next payday"), "");
+        pending.due.as_mut().unwrap().string = "next payday".to_string();
+
+        assert!(due_date(&pending).is_none());
+        assert!(due_today(&[pending], date("2026-10-18")).is_empty());
+    }
+}
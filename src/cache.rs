@@ -0,0 +1,750 @@
+// ##### Offline Cache #####
+
+// These are the functions that keep a
+// copy of the store on disk, next to the
+// config file, along with an outbox of
+// changes that have not reached Todoist
+// yet. Changes are applied to the store
+// straight away and replayed in order
+// once there is a connection again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::config;
+use crate::conflict::{self, Conflict, ConflictPolicy};
+use crate::error::CotoError;
+use crate::model::{Project, Section, Task, Due};
+use crate::query::TodoistClient;
+use crate::sync::Store;
+
+const STORE_FILE: &str = "cache.json";
+const OUTBOX_FILE: &str = "outbox.json";
+
+/// A change to a project, section or task. Created objects get a
+/// negative temporary id until Todoist hands back the real one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Mutation {
+    NewProject { temp_id: i64, args: Value },
+    UpdateProject { id: i64, args: Value },
+    DeleteProject { id: i64 },
+    NewSection { temp_id: i64, args: Value },
+    UpdateSection { id: i64, args: Value },
+    DeleteSection { id: i64 },
+    NewTask { temp_id: i64, args: Value },
+    /// `base` is the task as last synced, which conflicts are judged against
+    UpdateTask {
+        id: i64,
+        args: Value,
+        #[serde(default)]
+        base: Option<Box<Task>>,
+    },
+    CloseTask { id: i64 },
+    DeleteTask { id: i64 },
+    /// `args` holds the new `section_id`, or the `project_id` to leave every section.
+    /// `base` is kept the same way as for `UpdateTask`.
+    MoveTask {
+        id: i64,
+        args: Value,
+        #[serde(default)]
+        base: Option<Box<Task>>,
+    },
+    /// New positions for tasks, as (id, order) pairs
+    ReorderTasks { orders: Vec<(i64, i64)> },
+}
+
+/// A mutation waiting in the outbox, with the X-Request-Id it is always sent with
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueuedMutation {
+    pub request_id: Uuid,
+    pub mutation: Mutation,
+}
+
+/// The outcome of replaying the outbox
+#[derive(Default)]
+pub struct FlushReport {
+    pub sent: usize,
+    /// Changes Todoist refused outright, which have been dropped
+    pub rejected: Vec<(QueuedMutation, CotoError)>,
+    /// Why replaying stopped with changes still queued
+    pub stopped: Option<CotoError>,
+    /// A clash waiting on a manual merge, for the oldest queued change
+    pub conflict: Option<Conflict>,
+}
+
+/// What came of sending one queued change
+pub enum Attempt {
+    /// Todoist applied it, handing back the id of anything it created
+    Sent(Option<i64>),
+    /// The task was also changed on Todoist since the last sync
+    Conflict(Box<Conflict>),
+    Failed(CotoError),
+}
+
+/// Changes made locally that still have to reach Todoist, oldest first
+#[derive(Serialize, Deserialize, Default)]
+pub struct Outbox {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    next_temp_id: i64,
+    pub queue: Vec<QueuedMutation>,
+}
+
+impl Mutation {
+    /// The temporary id of whatever this creates
+    pub fn temp_id(&self) -> Option<i64> {
+        match self {
+            Mutation::NewProject { temp_id, .. }
+            | Mutation::NewSection { temp_id, .. }
+            | Mutation::NewTask { temp_id, .. } => Some(*temp_id),
+            _ => None,
+        }
+    }
+
+    /// The task an edit changes, with its args and the synced copy it is judged against
+    pub fn task_edit(&self) -> Option<(i64, &Value, &Option<Box<Task>>)> {
+        match self {
+            Mutation::UpdateTask { id, args, base }
+            | Mutation::MoveTask { id, args, base } => Some((*id, args, base)),
+            _ => None,
+        }
+    }
+
+    pub fn task_edit_mut(&mut self) -> Option<(i64, &mut Value, &mut Option<Box<Task>>)> {
+        match self {
+            Mutation::UpdateTask { id, args, base }
+            | Mutation::MoveTask { id, args, base } => Some((*id, args, base)),
+            _ => None,
+        }
+    }
+
+    /// Whether this was only ever meant to make something go away, so
+    /// Todoist no longer having it means there is nothing left to do
+    fn done_if_gone(&self) -> bool {
+        matches!(self, Mutation::DeleteProject { .. } | Mutation::DeleteSection { .. }
+            | Mutation::CloseTask { .. } | Mutation::DeleteTask { .. })
+    }
+
+    /// Point any reference to `temp_id` at the real id Todoist assigned
+    fn remap(&mut self, temp_id: i64, real_id: i64) {
+        match self {
+            Mutation::UpdateProject { id, args }
+            | Mutation::UpdateSection { id, args }
+            | Mutation::UpdateTask { id, args, .. }
+            | Mutation::MoveTask { id, args, .. } => {
+                if *id == temp_id {
+                    *id = real_id;
+                }
+                remap_args(args, temp_id, real_id);
+            }
+            Mutation::DeleteProject { id }
+            | Mutation::DeleteSection { id }
+            | Mutation::CloseTask { id }
+            | Mutation::DeleteTask { id } => {
+                if *id == temp_id {
+                    *id = real_id;
+                }
+            }
+            Mutation::NewProject { args, .. }
+            | Mutation::NewSection { args, .. }
+            | Mutation::NewTask { args, .. } => remap_args(args, temp_id, real_id),
+            Mutation::ReorderTasks { orders } => {
+                for (id, _) in orders.iter_mut() {
+                    if *id == temp_id {
+                        *id = real_id;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send this change through the REST API, returning the id of anything created
+    async fn replay(&self, client: &TodoistClient) -> Result<Option<i64>, CotoError> {
+        match self {
+            Mutation::NewProject { args, .. } => Ok(Some(client.new_project(args.to_string()).await?.id)),
+            Mutation::UpdateProject { id, args } => client.update_project(*id, args.to_string()).await.map(|_| None),
+            Mutation::DeleteProject { id } => client.delete_project(*id).await.map(|_| None),
+            Mutation::NewSection { args, .. } => Ok(Some(client.new_section(args.to_string()).await?.id)),
+            Mutation::UpdateSection { id, args } => client.update_section(*id, args.to_string()).await.map(|_| None),
+            Mutation::DeleteSection { id } => client.delete_section(*id).await.map(|_| None),
+            Mutation::NewTask { args, .. } => Ok(Some(client.new_task(args.to_string()).await?.id)),
+            Mutation::UpdateTask { id, args, .. } => client.update_task(*id, args.to_string()).await.map(|_| None),
+            Mutation::CloseTask { id } => client.close_task(*id).await.map(|_| None),
+            Mutation::DeleteTask { id } => client.delete_task(*id).await.map(|_| None),
+            Mutation::MoveTask { id, args, .. } => client.move_task(*id, args.clone()).await.map(|_| None),
+            Mutation::ReorderTasks { orders } => client.reorder_tasks(orders).await.map(|_| None),
+        }
+    }
+}
+
+fn remap_args(args: &mut Value, temp_id: i64, real_id: i64) {
+    for key in ["project_id", "section_id", "parent_id"].iter() {
+        if args[*key].as_i64() == Some(temp_id) {
+            args[*key] = Value::from(real_id);
+        }
+    }
+}
+
+/// Delete the cached store and the outbox, for when the API key is removed and
+/// they may belong to another account. Returns how many queued changes went.
+pub fn clear() -> Result<usize, CotoError> {
+    let dir = match config::config_dir() {
+        Some(dir) => dir,
+        None => return Ok(0),
+    };
+
+    let dropped = Outbox::load()?.len();
+    for file in [STORE_FILE, OUTBOX_FILE].iter() {
+        match fs::remove_file(dir.join(file)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(dropped)
+}
+
+impl Outbox {
+    /// Load the outbox from the config directory, starting empty if there is none
+    pub fn load() -> Result<Self, CotoError> {
+        let path = match config::config_dir() {
+            Some(dir) => dir.join(OUTBOX_FILE),
+            None => return Ok(Outbox::default()),
+        };
+
+        let mut outbox: Outbox = read_json(&path)?.unwrap_or_default();
+        outbox.path = Some(path);
+        Ok(outbox)
+    }
+
+    pub fn save(&self) -> Result<(), CotoError> {
+        match &self.path {
+            Some(path) => write_json(path, self),
+            None => Ok(()),
+        }
+    }
+
+    /// A fresh temporary id for something created offline
+    pub fn temp_id(&mut self) -> i64 {
+        self.next_temp_id -= 1;
+        self.next_temp_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Queue a change and write the outbox out before anything is sent
+    pub fn push(&mut self, mutation: Mutation) -> Result<(), CotoError> {
+        self.queue.push(QueuedMutation {
+            request_id: Uuid::new_v4(),
+            mutation,
+        });
+        self.save()
+    }
+
+    /// Replay queued changes in order until the outbox is empty or Todoist
+    /// can't be reached. Todoist refusing a change drops it into the report
+    /// so one bad change can't hold up the rest.
+    pub async fn flush(&mut self, client: &TodoistClient, store: &mut Store, policy: ConflictPolicy) -> FlushReport {
+        let mut report = FlushReport::default();
+
+        while let Some(queued) = self.queue.first().cloned() {
+            let attempt = queued.attempt(client).await;
+            if !self.settle_attempt(store, &queued, attempt, policy, &mut report) {
+                break;
+            }
+        }
+
+        report
+    }
+
+    /// Act on an attempt to send `queued`, which was at the head of the
+    /// outbox when it went out. Returns whether to go on to the next change.
+    pub fn settle_attempt(&mut self, store: &mut Store, queued: &QueuedMutation, attempt: Attempt,
+        policy: ConflictPolicy, report: &mut FlushReport) -> bool {
+        // Settled some other way while it was in flight, so try the new head
+        if self.queue.first().map(|head| head.request_id) != Some(queued.request_id) {
+            return true;
+        }
+
+        match attempt {
+            Attempt::Conflict(found) => {
+                if policy == ConflictPolicy::Manual {
+                    report.conflict = Some(*found);
+                    return false;
+                }
+                if let Err(e) = conflict::settle(self, &found, policy, &[]) {
+                    report.stopped = Some(e);
+                    return false;
+                }
+                return true;
+            }
+            Attempt::Sent(created) => {
+                if let (Some(temp_id), Some(real_id)) = (queued.mutation.temp_id(), created) {
+                    for pending in self.queue.iter_mut() {
+                        pending.mutation.remap(temp_id, real_id);
+                    }
+                    store.remap(temp_id, real_id);
+                }
+                report.sent += 1;
+            }
+            // Still offline or Todoist is struggling, try again later
+            Attempt::Failed(e) if e.is_transient() => {
+                report.stopped = Some(e);
+                return false;
+            }
+            // Already deleted or completed on Todoist, as this meant it to be
+            Attempt::Failed(CotoError::NotFound) if queued.mutation.done_if_gone() => report.sent += 1,
+            Attempt::Failed(e) => report.rejected.push((queued.clone(), e)),
+        }
+
+        self.queue.remove(0);
+        if let Err(e) = self.save() {
+            report.stopped = Some(e);
+            return false;
+        }
+        true
+    }
+}
+
+impl QueuedMutation {
+    /// Send this change, first checking a task edit against Todoist's copy.
+    /// Only the network is touched, so this can run off the UI thread.
+    pub async fn attempt(&self, client: &TodoistClient) -> Attempt {
+        if let Some((id, args, Some(base))) = self.mutation.task_edit() {
+            match client.get_task(id).await {
+                Ok(server) => {
+                    if let Some(found) = conflict::detect(base, args, &server) {
+                        return Attempt::Conflict(Box::new(found));
+                    }
+                }
+                // Gone from Todoist, so replaying it below rejects it
+                Err(CotoError::NotFound) => {}
+                Err(e) => return Attempt::Failed(e),
+            }
+        }
+
+        match self.mutation.replay(&client.with_request_id(self.request_id)).await {
+            Ok(created) => Attempt::Sent(created),
+            Err(e) => Attempt::Failed(e),
+        }
+    }
+}
+
+/// Apply a change to the store and queue it for Todoist, remembering the
+/// last synced copy of any task it edits so conflicts can be spotted later
+pub fn record(store: &mut Store, outbox: &mut Outbox, mut mutation: Mutation) -> Result<(), CotoError> {
+    if let Some((id, _, base)) = mutation.task_edit_mut() {
+        // An earlier queued edit already holds the synced copy
+        let earlier = outbox.queue.iter().find_map(|queued| match queued.mutation.task_edit() {
+            Some((queued_id, _, Some(queued_base))) if queued_id == id => Some(queued_base.clone()),
+            _ => None,
+        });
+        let created_offline = outbox.queue.iter().any(|queued| queued.mutation.temp_id() == Some(id));
+
+        if !created_offline {
+            *base = earlier.or_else(|| store.tasks.get(&id).cloned().map(Box::new));
+        }
+    }
+
+    store.apply_mutation(&mutation);
+    outbox.push(mutation)?;
+    store.save()
+}
+
+impl Store {
+    /// Load the last synced store from the config directory
+    pub fn load() -> Result<Self, CotoError> {
+        match config::config_dir() {
+            Some(dir) => Ok(read_json(&dir.join(STORE_FILE))?.unwrap_or_default()),
+            None => Ok(Store::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), CotoError> {
+        match config::config_dir() {
+            Some(dir) => write_json(&dir.join(STORE_FILE), self),
+            None => Ok(()),
+        }
+    }
+
+    /// Show a change locally before Todoist has seen it
+    pub fn apply_mutation(&mut self, mutation: &Mutation) {
+        match mutation {
+            Mutation::NewProject { temp_id, args } => {
+                let order = self.projects.values().map(|project| project.order).max().unwrap_or(0) + 1;
+                self.projects.insert(*temp_id, Project {
+                    id: *temp_id,
+                    name: args["name"].as_str().unwrap_or("").to_string(),
+                    color: args["color"].as_i64().unwrap_or(0),
+                    parent_id: args["parent_id"].as_i64(),
+                    order,
+                    comment_count: 0,
+                    shared: false,
+                    favorite: args["favorite"].as_bool().unwrap_or(false),
+                    inbox_project: false,
+                    team_inbox: false,
+                });
+            }
+            Mutation::UpdateProject { id, args } => {
+                if let Some(project) = self.projects.get_mut(id) {
+                    if let Some(name) = args["name"].as_str() {
+                        project.name = name.to_string();
+                    }
+                    if let Some(color) = args["color"].as_i64() {
+                        project.color = color;
+                    }
+                    if let Some(favorite) = args["favorite"].as_bool() {
+                        project.favorite = favorite;
+                    }
+                }
+            }
+            Mutation::DeleteProject { id } => {
+                self.projects.remove(id);
+                self.sections.retain(|_, section| section.project_id != *id);
+                self.tasks.retain(|_, task| task.project_id != *id);
+            }
+            Mutation::NewSection { temp_id, args } => {
+                let project_id = args["project_id"].as_i64().unwrap_or(0);
+                let order = self.sections.values()
+                    .filter(|section| section.project_id == project_id)
+                    .map(|section| section.order)
+                    .max()
+                    .unwrap_or(0) + 1;
+                self.sections.insert(*temp_id, Section {
+                    id: *temp_id,
+                    project_id,
+                    order: args["order"].as_i64().unwrap_or(order),
+                    name: args["name"].as_str().unwrap_or("").to_string(),
+                });
+            }
+            Mutation::UpdateSection { id, args } => {
+                if let Some(section) = self.sections.get_mut(id) {
+                    if let Some(name) = args["name"].as_str() {
+                        section.name = name.to_string();
+                    }
+                }
+            }
+            Mutation::DeleteSection { id } => {
+                self.sections.remove(id);
+                self.tasks.retain(|_, task| task.section_id != *id);
+            }
+            Mutation::NewTask { temp_id, args } => {
+                let mut task = Task {
+                    id: *temp_id,
+                    project_id: args["project_id"].as_i64()
+                        .or_else(|| self.projects.values().find(|project| project.inbox_project).map(|project| project.id))
+                        .unwrap_or(0),
+                    section_id: args["section_id"].as_i64().unwrap_or(0),
+                    content: String::new(),
+                    completed: false,
+                    label_ids: Vec::new(),
+                    parent_id: args["parent_id"].as_i64(),
+                    order: args["order"].as_i64().unwrap_or(0),
+                    priority: 1,
+                    due: None,
+                    url: String::new(),
+                    comment_count: 0,
+                    created: String::new(),
+                };
+                update_task_fields(&mut task, args);
+                self.tasks.insert(*temp_id, task);
+            }
+            Mutation::UpdateTask { id, args, .. } => {
+                if let Some(task) = self.tasks.get_mut(id) {
+                    update_task_fields(task, args);
+                }
+            }
+            Mutation::CloseTask { id } | Mutation::DeleteTask { id } => {
+                self.tasks.remove(id);
+            }
+            Mutation::MoveTask { id, args, .. } => {
+                if let Some(task) = self.tasks.get_mut(id) {
+                    match (args["section_id"].as_i64(), args["project_id"].as_i64()) {
+                        (Some(section_id), _) => task.section_id = section_id,
+                        (None, Some(project_id)) => {
+                            task.project_id = project_id;
+                            task.section_id = 0;
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+            Mutation::ReorderTasks { orders } => {
+                for (id, order) in orders.iter() {
+                    if let Some(task) = self.tasks.get_mut(id) {
+                        task.order = *order;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Swap a temporary id for the real one everywhere it appears
+    pub fn remap(&mut self, temp_id: i64, real_id: i64) {
+        if let Some(mut project) = self.projects.remove(&temp_id) {
+            project.id = real_id;
+            self.projects.insert(real_id, project);
+        }
+        if let Some(mut section) = self.sections.remove(&temp_id) {
+            section.id = real_id;
+            self.sections.insert(real_id, section);
+        }
+        if let Some(mut task) = self.tasks.remove(&temp_id) {
+            task.id = real_id;
+            self.tasks.insert(real_id, task);
+        }
+
+        for section in self.sections.values_mut() {
+            if section.project_id == temp_id {
+                section.project_id = real_id;
+            }
+        }
+        for task in self.tasks.values_mut() {
+            if task.project_id == temp_id {
+                task.project_id = real_id;
+            }
+            if task.section_id == temp_id {
+                task.section_id = real_id;
+            }
+            if task.parent_id == Some(temp_id) {
+                task.parent_id = Some(real_id);
+            }
+        }
+    }
+}
+
+/// Copy the fields the REST API accepts for a task onto a local copy
+fn update_task_fields(task: &mut Task, args: &Value) {
+    if let Some(content) = args["content"].as_str() {
+        task.content = content.to_string();
+    }
+    if let Some(priority) = args["priority"].as_i64() {
+        task.priority = priority;
+    }
+    if let Some(label_ids) = args["label_ids"].as_array() {
+        task.label_ids = label_ids.iter().filter_map(|id| id.as_i64()).collect();
+    }
+    if let Some(order) = args["order"].as_i64() {
+        task.order = order;
+    }
+
+    // An empty due string or date clears the due date
+    let due_date = args["due_date"].as_str().or_else(|| args["due_datetime"].as_str());
+    let due_string = args["due_string"].as_str();
+    match (due_date, due_string) {
+        (Some(""), _) | (_, Some("")) => task.due = None,
+        (Some(date), _) => {
+            task.due = Some(Due {
+                string: date.to_string(),
+                date: date.split('T').next().unwrap_or(date).to_string(),
+                recurring: false,
+                datetime: if date.contains('T') { Some(date.to_string()) } else { None },
+                timezone: None,
+            });
+        }
+        // Natural language dates are only understood once Todoist sees them
+        (None, Some(string)) => {
+            task.due = Some(Due {
+                string: string.to_string(),
+                date: String::new(),
+                recurring: false,
+                datetime: None,
+                timezone: None,
+            });
+        }
+        (None, None) => {}
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, CotoError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write through a temporary file so a crash never leaves half a file behind
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), CotoError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string(value)?)?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::test_support::{due, project, section, task};
+
+    fn store() -> Store {
+        let mut store = Store::default();
+        let mut inbox = project(1, "Inbox", None);
+        inbox.inbox_project = true;
+        store.projects.insert(1, inbox);
+        store.projects.insert(2, project(2, "Work", None));
+        store.sections.insert(5, Section { id: 5, project_id: 2, order: 1, name: "Next".to_string() });
+        store.tasks.insert(10, task(10, 1, "Buy milk"));
+        store.tasks.insert(11, section(task(11, 2, "Write report"), 5));
+        store
+    }
+
+    /// Settle the head of the outbox as if `attempt` came back for it
+    fn settle(outbox: &mut Outbox, store: &mut Store, attempt: Attempt, report: &mut FlushReport) -> bool {
+        let head = outbox.queue[0].clone();
+        outbox.settle_attempt(store, &head, attempt, ConflictPolicy::Manual, report)
+    }
+
+    #[test]
+    fn new_task_goes_to_the_inbox() {
+        let mut store = store();
+        store.apply_mutation(&Mutation::NewTask {
+            temp_id: -1,
+            args: json!({"content": "Pay rent", "priority": 4, "due_date": "2026-10-20"}),
+        });
+
+        let created = &store.tasks[&-1];
+        assert_eq!(created.project_id, 1);
+        assert_eq!(created.content, "Pay rent");
+        assert_eq!(created.priority, 4);
+        assert_eq!(created.due.as_ref().map(|due| due.date.as_str()), Some("2026-10-20"));
+    }
+
+    #[test]
+    fn update_task_changes_only_what_is_given() {
+        let mut store = store();
+        store.tasks.insert(10, due(task(10, 1, "Buy milk"), "2026-10-20"));
+        store.apply_mutation(&Mutation::UpdateTask { id: 10, args: json!({"content": "Buy oat milk"}), base: None });
+        assert_eq!(store.tasks[&10].content, "Buy oat milk");
+        assert!(store.tasks[&10].due.is_some());
+
+        store.apply_mutation(&Mutation::UpdateTask { id: 10, args: json!({"due_string": ""}), base: None });
+        assert!(store.tasks[&10].due.is_none());
+    }
+
+    #[test]
+    fn moving_to_a_project_leaves_the_section() {
+        let mut store = store();
+        store.apply_mutation(&Mutation::MoveTask { id: 11, args: json!({"project_id": 1}), base: None });
+
+        assert_eq!(store.tasks[&11].project_id, 1);
+        assert_eq!(store.tasks[&11].section_id, 0);
+    }
+
+    #[test]
+    fn deleting_a_project_takes_its_sections_and_tasks() {
+        let mut store = store();
+        store.apply_mutation(&Mutation::DeleteProject { id: 2 });
+
+        assert!(!store.projects.contains_key(&2));
+        assert!(store.sections.is_empty());
+        assert_eq!(store.tasks.keys().collect::<Vec<_>>(), vec![&10]);
+    }
+
+    #[test]
+    fn real_id_replaces_the_temp_id_in_later_changes() {
+        let mut store = store();
+        let mut outbox = Outbox::default();
+        let created = Mutation::NewTask { temp_id: -1, args: json!({"content": "Plan trip"}) };
+        store.apply_mutation(&created);
+        outbox.push(created).unwrap();
+        outbox.push(Mutation::UpdateTask { id: -1, args: json!({"priority": 2}), base: None }).unwrap();
+        outbox.push(Mutation::NewTask { temp_id: -2, args: json!({"content": "Book train", "parent_id": -1}) }).unwrap();
+
+        let mut report = FlushReport::default();
+        assert!(settle(&mut outbox, &mut store, Attempt::Sent(Some(100)), &mut report));
+
+        assert_eq!(report.sent, 1);
+        assert_eq!(outbox.len(), 2);
+        assert!(matches!(outbox.queue[0].mutation, Mutation::UpdateTask { id: 100, .. }));
+        match &outbox.queue[1].mutation {
+            Mutation::NewTask { args, .. } => assert_eq!(args["parent_id"], json!(100)),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(store.tasks.contains_key(&100));
+        assert!(!store.tasks.contains_key(&-1));
+    }
+
+    #[test]
+    fn transient_failure_stops_with_the_change_still_queued() {
+        let mut store = store();
+        let mut outbox = Outbox::default();
+        outbox.push(Mutation::CloseTask { id: 10 }).unwrap();
+        outbox.push(Mutation::DeleteTask { id: 11 }).unwrap();
+
+        let mut report = FlushReport::default();
+        let attempt = Attempt::Failed(CotoError::Server { status: 503 });
+        assert!(!settle(&mut outbox, &mut store, attempt, &mut report));
+
+        assert_eq!(outbox.len(), 2);
+        assert!(matches!(report.stopped, Some(CotoError::Server { status: 503 })));
+        assert!(report.rejected.is_empty());
+    }
+
+    #[test]
+    fn refused_change_is_dropped_and_the_rest_go_on() {
+        let mut store = store();
+        let mut outbox = Outbox::default();
+        outbox.push(Mutation::UpdateTask { id: 10, args: json!({"priority": 9}), base: None }).unwrap();
+        outbox.push(Mutation::CloseTask { id: 11 }).unwrap();
+
+        let mut report = FlushReport::default();
+        let attempt = Attempt::Failed(CotoError::Api { status: 400, message: "bad priority".to_string() });
+        assert!(settle(&mut outbox, &mut store, attempt, &mut report));
+
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(report.sent, 0);
+        assert_eq!(report.rejected.len(), 1);
+        assert!(report.stopped.is_none());
+    }
+
+    #[test]
+    fn closing_or_deleting_what_is_gone_counts_as_sent() {
+        let mut store = store();
+        let mut outbox = Outbox::default();
+        outbox.push(Mutation::CloseTask { id: 10 }).unwrap();
+        outbox.push(Mutation::UpdateTask { id: 11, args: json!({"content": "Write it"}), base: None }).unwrap();
+
+        let mut report = FlushReport::default();
+        assert!(settle(&mut outbox, &mut store, Attempt::Failed(CotoError::NotFound), &mut report));
+        assert_eq!(report.sent, 1);
+        assert!(report.rejected.is_empty());
+
+        // An edit to something gone is still lost, so it is reported
+        assert!(settle(&mut outbox, &mut store, Attempt::Failed(CotoError::NotFound), &mut report));
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.rejected.len(), 1);
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn change_settled_while_in_flight_is_left_alone() {
+        let mut store = store();
+        let mut outbox = Outbox::default();
+        outbox.push(Mutation::CloseTask { id: 10 }).unwrap();
+        let in_flight = outbox.queue[0].clone();
+        outbox.queue.clear();
+        outbox.push(Mutation::CloseTask { id: 11 }).unwrap();
+
+        let mut report = FlushReport::default();
+        assert!(outbox.settle_attempt(&mut store, &in_flight, Attempt::Sent(None), ConflictPolicy::Manual, &mut report));
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(report.sent, 0);
+    }
+}
@@ -0,0 +1,411 @@
+// ##### Command Line Subcommands #####
+
+// These are the non-interactive commands
+// that let coto be used from scripts,
+// cron and Makefiles. Each one wraps
+// the REST API functions in query.rs.
+
+use serde_json::{json, Map, Value};
+use structopt::StructOpt;
+use tokio::runtime::Runtime;
+
+use crate::agenda;
+use crate::cache::Outbox;
+use crate::config::Config;
+use crate::error::CotoError;
+use crate::model::{Project, Section, Task, Label};
+use crate::output::{self, Format, GroupedTask};
+use crate::query::TodoistClient;
+use crate::sync::Store;
+use crate::theme::{self, ThemeCheck};
+use crate::view::{self, Group, SavedView};
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// List projects
+    Projects {
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// Create, rename or remove a project
+    Project(ProjectCommand),
+    /// List the sections of a project
+    Sections {
+        /// Project name or id
+        #[structopt(short, long)]
+        project: String,
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// Create, rename or remove a section
+    Section(SectionCommand),
+    /// List tasks, optionally only those in one project or matching a filter
+    Tasks {
+        /// Project name or id
+        #[structopt(short, long)]
+        project: Option<String>,
+        /// Todoist filter query, e.g. "(today | overdue) & #Work & p1"
+        #[structopt(long, conflicts_with = "project")]
+        filter: Option<String>,
+        /// Language the filter is written in, if not English (e.g. de)
+        #[structopt(long, requires = "filter")]
+        lang: Option<String>,
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// List overdue tasks and those due today, across every project
+    Today {
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// List tasks due in the next few days, across every project
+    Upcoming {
+        /// How many days ahead to look, today included
+        #[structopt(short, long, default_value = "7")]
+        days: i64,
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// List the tasks of a view saved in the config, sorted and grouped as it says
+    View {
+        name: String,
+        #[structopt(flatten)]
+        output: Output,
+    },
+    /// Add a task
+    Add {
+        content: String,
+        /// Project name or id, the Inbox if left out
+        #[structopt(short, long)]
+        project: Option<String>,
+        /// Section name or id within the project
+        #[structopt(short, long)]
+        section: Option<String>,
+        #[structopt(flatten)]
+        fields: TaskFields,
+    },
+    /// Complete a task
+    Done { id: i64 },
+    /// Change a task
+    Edit {
+        id: i64,
+        /// New content
+        #[structopt(short, long)]
+        content: Option<String>,
+        #[structopt(flatten)]
+        fields: TaskFields,
+    },
+    /// Delete a task
+    Rm { id: i64 },
+    /// Check color themes
+    Theme(ThemeCommand),
+}
+
+#[derive(StructOpt)]
+pub enum ProjectCommand {
+    /// Create a project
+    Add { name: String },
+    /// Rename a project
+    Edit { id: i64, name: String },
+    /// Delete a project and everything in it
+    Rm { id: i64 },
+}
+
+#[derive(StructOpt)]
+pub enum SectionCommand {
+    /// Create a section
+    Add {
+        /// Project name or id
+        #[structopt(short, long)]
+        project: String,
+        name: String,
+    },
+    /// Rename a section
+    Edit { id: i64, name: String },
+    /// Delete a section and its tasks
+    Rm { id: i64 },
+}
+
+#[derive(StructOpt)]
+pub enum ThemeCommand {
+    /// Validate a theme's colors and check its contrast, or every theme's
+    Check {
+        /// Bundled or user theme name
+        name: Option<String>,
+    },
+}
+
+// Output options shared by the listing commands
+#[derive(StructOpt)]
+pub struct Output {
+    /// Output format: json, csv, tsv or table
+    #[structopt(short, long, default_value = "table")]
+    format: Format,
+}
+
+// Task fields shared by `add` and `edit`
+#[derive(StructOpt)]
+pub struct TaskFields {
+    /// Priority, 1 (normal) to 4 (urgent)
+    #[structopt(short = "P", long)]
+    priority: Option<i64>,
+    /// Due date in any form Todoist understands, e.g. "tomorrow 5pm"
+    #[structopt(short, long)]
+    due: Option<String>,
+    /// Label name, repeat for more than one
+    #[structopt(short, long = "label")]
+    labels: Vec<String>,
+}
+
+impl Command {
+    /// Whether this talks to Todoist at all, and so needs an API key
+    pub fn needs_key(&self) -> bool {
+        !matches!(self, Command::Theme(_))
+    }
+
+    /// Whether this changes anything on Todoist
+    fn changes_todoist(&self) -> bool {
+        matches!(self, Command::Project(_) | Command::Section(_) | Command::Add { .. }
+            | Command::Done { .. } | Command::Edit { .. } | Command::Rm { .. })
+    }
+}
+
+/// Run a subcommand to completion, printing its results to stdout
+pub fn run(client: &TodoistClient, config: &Config, outbox: &Outbox, command: Command) -> Result<(), CotoError> {
+    // Sent straight away, this could land before changes queued earlier and
+    // then be overwritten when they are replayed
+    if command.changes_todoist() && !outbox.is_empty() {
+        return Err(usage(&format!("{} offline change(s) are still queued, send them first by opening coto \
+            or with `coto --resolve server|local|manual`", outbox.len())));
+    }
+
+    let mut runtime = Runtime::new().expect("Could not start runtime");
+    runtime.block_on(run_command(client, config, command))
+}
+
+async fn run_command(client: &TodoistClient, config: &Config, command: Command) -> Result<(), CotoError> {
+    match command {
+        Command::Projects { output } => {
+            output::print(&client.get_all_projects().await?, output.format);
+        }
+        Command::Project(ProjectCommand::Add { name }) => {
+            let project = client.new_project(json!({"name": name}).to_string()).await?;
+            println!("{}", project.id);
+        }
+        Command::Project(ProjectCommand::Edit { id, name }) => {
+            client.update_project(id, json!({"name": name}).to_string()).await?;
+        }
+        Command::Project(ProjectCommand::Rm { id }) => {
+            client.delete_project(id).await?;
+        }
+        Command::Sections { project, output } => {
+            let project = find_project(client, &project).await?;
+            output::print(&client.get_project_sections(project.id).await?, output.format);
+        }
+        Command::Section(SectionCommand::Add { project, name }) => {
+            let project = find_project(client, &project).await?;
+            let json_name_and_id = json!({"name": name, "project_id": project.id}).to_string();
+            let section = client.new_section(json_name_and_id).await?;
+            println!("{}", section.id);
+        }
+        Command::Section(SectionCommand::Edit { id, name }) => {
+            client.update_section(id, json!({"name": name}).to_string()).await?;
+        }
+        Command::Section(SectionCommand::Rm { id }) => {
+            client.delete_section(id).await?;
+        }
+        Command::Tasks { project, filter, lang, output } => {
+            let tasks = match (project, filter) {
+                (Some(project), _) => client.get_project_tasks(find_project(client, &project).await?.id).await?,
+                (None, Some(filter)) => {
+                    let lang = lang.or_else(|| config.filter_lang.clone());
+                    view_tasks(client, &SavedView::query(&filter), lang.as_deref()).await?
+                }
+                (None, None) => client.get_all_tasks(None, None).await?,
+            };
+            output::print(&tasks, output.format);
+        }
+        Command::Today { output } => {
+            let tasks = client.get_all_tasks(None, None).await?;
+            output::print(&agenda::due_today(&tasks, agenda::today()), output.format);
+        }
+        Command::Upcoming { days, output } => {
+            if days < 1 {
+                return Err(usage("--days must be at least 1"));
+            }
+            let tasks = client.get_all_tasks(None, None).await?;
+            let upcoming: Vec<Task> = agenda::upcoming(&tasks, agenda::today(), days)
+                .into_iter()
+                .flat_map(|(_, tasks)| tasks)
+                .collect();
+            output::print(&upcoming, output.format);
+        }
+        Command::View { name, output } => {
+            let view = find_view(config, &name)?;
+            let tasks = view_tasks(client, view, config.filter_lang.as_deref()).await?;
+            let (projects, labels) = match view.group {
+                Group::Project | Group::Label => match (client.get_all_projects().await, client.get_all_labels().await) {
+                    (Ok(projects), Ok(labels)) => (projects, labels),
+                    _ => {
+                        let store = Store::load()?;
+                        (store.projects(), store.labels())
+                    }
+                },
+                _ => (Vec::new(), Vec::new()),
+            };
+            let arranged: Vec<GroupedTask> = view::arrange(view, tasks, &projects, &labels, agenda::today())
+                .into_iter()
+                .flat_map(|(group, tasks)| tasks.into_iter().map(move |task| GroupedTask { group: group.clone(), task }))
+                .collect();
+            output::print(&arranged, output.format);
+        }
+        Command::Add { content, project, section, fields } => {
+            let mut json_data = task_json(client, &fields).await?;
+            json_data.insert("content".to_string(), Value::from(content));
+            if let Some(project) = &project {
+                let project = find_project(client, project).await?;
+                json_data.insert("project_id".to_string(), Value::from(project.id));
+                if let Some(section) = &section {
+                    let section = find_section(client, project.id, section).await?;
+                    json_data.insert("section_id".to_string(), Value::from(section.id));
+                }
+            } else if section.is_some() {
+                return Err(usage("--section needs --project"));
+            }
+            let task = client.new_task(Value::Object(json_data).to_string()).await?;
+            println!("{}", task.id);
+        }
+        Command::Done { id } => {
+            client.close_task(id).await?;
+        }
+        Command::Edit { id, content, fields } => {
+            let mut json_data = task_json(client, &fields).await?;
+            if let Some(content) = content {
+                json_data.insert("content".to_string(), Value::from(content));
+            }
+            if json_data.is_empty() {
+                return Err(usage("nothing to change"));
+            }
+            client.update_task(id, Value::Object(json_data).to_string()).await?;
+        }
+        Command::Rm { id } => {
+            client.delete_task(id).await?;
+        }
+        Command::Theme(ThemeCommand::Check { name }) => {
+            check_themes(name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print what checking each theme found, failing if any has errors
+fn check_themes(name: Option<String>) -> Result<(), CotoError> {
+    let names = match name {
+        Some(name) => vec![name],
+        None => theme::names(),
+    };
+
+    let mut failed = 0;
+    for name in names.iter() {
+        let check = match theme::check(name) {
+            Ok(check) => check,
+            Err(e) => ThemeCheck { errors: vec![e.to_string()], warnings: Vec::new() },
+        };
+        if check.errors.is_empty() && check.warnings.is_empty() {
+            println!("{}: ok", name);
+            continue;
+        }
+
+        println!("{}:", name);
+        for error in check.errors.iter() {
+            println!("  error: {}", error);
+        }
+        for warning in check.warnings.iter() {
+            println!("  warning: {}", warning);
+        }
+        if !check.errors.is_empty() {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(usage(&format!("{} theme(s) have errors", failed)));
+    }
+    Ok(())
+}
+
+/// The priority, due date and labels of a task as REST arguments
+async fn task_json(client: &TodoistClient, fields: &TaskFields) -> Result<Map<String, Value>, CotoError> {
+    let mut json_data = Map::new();
+    if let Some(priority) = fields.priority {
+        if !(1..=4).contains(&priority) {
+            return Err(usage("priority must be between 1 and 4"));
+        }
+        json_data.insert("priority".to_string(), Value::from(priority));
+    }
+    if let Some(due) = &fields.due {
+        json_data.insert("due_string".to_string(), Value::from(due.as_str()));
+    }
+    if !fields.labels.is_empty() {
+        let labels = client.get_all_labels().await?;
+        let mut label_ids = Vec::new();
+        for name in fields.labels.iter() {
+            label_ids.push(find_label(&labels, name)?.id);
+        }
+        json_data.insert("label_ids".to_string(), json!(label_ids));
+    }
+
+    Ok(json_data)
+}
+
+/// Find a project by id or by name, ignoring case
+pub async fn find_project(client: &TodoistClient, project: &str) -> Result<Project, CotoError> {
+    client.get_all_projects().await?
+        .into_iter()
+        .find(|p| p.id.to_string() == project || p.name.eq_ignore_ascii_case(project))
+        .ok_or_else(|| usage(&format!("no project called `{}`", project)))
+}
+
+async fn find_section(client: &TodoistClient, project_id: i64, section: &str) -> Result<Section, CotoError> {
+    client.get_project_sections(project_id).await?
+        .into_iter()
+        .find(|s| s.id.to_string() == section || s.name.eq_ignore_ascii_case(section))
+        .ok_or_else(|| usage(&format!("no section called `{}`", section)))
+}
+
+fn find_label<'a>(labels: &'a [Label], name: &str) -> Result<&'a Label, CotoError> {
+    let name = name.trim_start_matches('@');
+    labels.iter()
+        .find(|label| label.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| usage(&format!("no label called `{}`", name)))
+}
+
+/// The tasks a view's filter picks, from the local cache when Todoist can't be reached
+async fn view_tasks(client: &TodoistClient, view: &SavedView, lang: Option<&str>) -> Result<Vec<Task>, CotoError> {
+    let filter = view.query_filter();
+    match client.get_all_tasks(filter, lang.filter(|_| filter.is_some())).await {
+        Err(CotoError::Network(_)) => {
+            eprintln!("Could not reach Todoist, using the local cache instead");
+            view::run_locally(view, &Store::load()?, agenda::today())
+        }
+        result => result,
+    }
+}
+
+fn find_view<'a>(config: &'a Config, name: &str) -> Result<&'a SavedView, CotoError> {
+    config.views.iter()
+        .find(|view| view.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = config.views.iter().map(|view| view.name.as_str()).collect();
+            if names.is_empty() {
+                usage(&format!("no view called `{}`, and none are saved under [[views]] in the config", name))
+            } else {
+                usage(&format!("no view called `{}`, the saved views are: {}", name, names.join(", ")))
+            }
+        })
+}
+
+fn usage(message: &str) -> CotoError {
+    CotoError::Usage(message.to_string())
+}
@@ -5,13 +5,36 @@
 // This is where the Todoist API key
 // is stored.
 
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
 use serde::{Serialize, Deserialize};
 use tokio::runtime::Runtime;
-use reqwest::{header, Client};
 
-#[derive(Serialize, Deserialize)]
+use crate::cache;
+use crate::error::CotoError;
+use crate::query::TodoistClient;
+use crate::theme;
+use crate::view::SavedView;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub todoist_key: String,
+    /// Overrides the Todoist REST API base URL (e.g. a local mock server)
+    pub base_url: Option<String>,
+    /// Overrides the Todoist Sync API endpoint
+    pub sync_url: Option<String>,
+    /// Name of the color theme picked in the settings screen
+    pub theme: String,
+    /// The language filter queries are written in, when not English (e.g. `de`)
+    pub filter_lang: Option<String>,
+    // toml can't write a plain value after a table, so tables go last
+    pub retry: Retry,
+    /// Named filters with their own sort, grouping and layout. Left out
+    /// when empty, as `views = []` would be a plain value after `[retry]`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<SavedView>,
 }
 
 /// Generate default config file
@@ -19,53 +42,85 @@ impl ::std::default::Default for Config {
     fn default() -> Self {
         Self {
             todoist_key: String::new(),
+            base_url: None,
+            sync_url: None,
+            retry: Retry::default(),
+            theme: theme::DEFAULT_THEME.to_string(),
+            filter_lang: None,
+            views: Vec::new(),
         }
     }
 }
 
-fn input_key() -> std::string::String {
-    let mut key: String = String::new();
-    let _input_key = std::io::stdin().read_line(&mut key).unwrap();
-    return key
+/// How hard to retry requests that were rate limited or hit a server error
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Retry {
+    /// Retries after the first attempt, 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every attempt after that
+    pub base_delay_ms: u64,
+    /// Upper bound on any single backoff delay
+    pub max_delay_ms: u64,
 }
 
-async fn validate_key(key: &String) -> Result<bool, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", key).parse().unwrap());
+impl ::std::default::Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
 
-    let res = Client::new()
-        .get("https://api.todoist.com/rest/v1/projects")
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+/// The directory confy keeps `coto.toml` in, which the cache shares
+pub fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("rs", "", "coto").map(|dirs| dirs.config_dir().to_path_buf())
+}
 
+fn input_key() -> Result<String, CotoError> {
+    let mut key: String = String::new();
+    match std::io::stdin().read_line(&mut key) {
+        Ok(0) => Err(CotoError::Usage("No API key was entered".to_string())),
+        Ok(_) => Ok(key.trim_end().to_string()),
+        Err(e) => Err(CotoError::Usage(format!("Could not read the API key: {}", e))),
+    }
+}
 
-    if res == String::from("Forbidden") {
-        Ok(false)
-    } else {
-        Ok(true)
+/// Ok(false) only when Todoist rejects the key, anything else is a real error
+async fn validate_key(client: &TodoistClient) -> Result<bool, CotoError> {
+    match client.get_all_projects().await {
+        Ok(_) => Ok(true),
+        Err(CotoError::Auth) => Ok(false),
+        Err(e) => Err(e),
     }
 }
 
-pub fn config_setup() -> Result<(), Box<dyn std::error::Error>> {
-    let config: Config = confy::load("coto").expect("Could not load config");
+/// Make sure there is an API key, asking for one if `ask` allows it.
+/// Subcommands don't ask, as there may be nobody there to answer.
+pub fn config_setup(ask: bool) -> Result<(), CotoError> {
+    let config: Config = confy::load("coto")?;
 
-    if config.todoist_key == "" {
+    if config.todoist_key.is_empty() && !ask {
+        return Err(CotoError::Usage("There is no Todoist API key yet, run `coto` on its own to enter one".to_string()));
+    }
+    if config.todoist_key.is_empty() {
         println!("Enter your Todoist API key: ");
 
         let mut success: bool = false;
         let mut key: String = String::new();
 
-        while success == false {
-            let mut key_input: String = input_key();
-            key_input = key_input[..key_input.len()-1].to_string();
+        while !success {
+            let key_input: String = input_key()?;
 
+            let client = TodoistClient::from_config(&Config {
+                todoist_key: key_input.clone(),
+                ..config.clone()
+            });
             success = Runtime::new().expect("Could not validate key")
-                .block_on(validate_key(&key_input))
-                .unwrap();
-            if success == true {
+                .block_on(validate_key(&client))?;
+            if success {
                 key = key_input;
             } else {
                 println!("Invalid Todoist API key")
@@ -73,17 +128,91 @@ pub fn config_setup() -> Result<(), Box<dyn std::error::Error>> {
         }
         let updated: Config = Config {
             todoist_key: key,
+            ..config
         };
 
-        confy::store("coto", updated).expect("Could not store config");
+        confy::store("coto", updated)?;
     }
 
     Ok(())
 }
 
-pub fn remove_key() {
+/// Forget the API key, along with the cache and outbox, which could
+/// otherwise be synced or replayed against a different account
+pub fn remove_key() -> Result<(), CotoError> {
     let config = Config {
         todoist_key: String::from(""),
+        ..confy::load("coto")?
     };
-    confy::store("coto", config).expect("Could not remove previous key");
+    confy::store("coto", config)?;
+
+    let dropped = cache::clear()?;
+    if dropped > 0 {
+        println!("Dropped {} queued change(s) made with the old key", dropped);
+    }
+
+    Ok(())
+}
+
+pub fn save_theme(name: &str) -> Result<(), CotoError> {
+    let config = Config {
+        theme: name.to_string(),
+        ..confy::load("coto")?
+    };
+    confy::store("coto", config)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::{Group, Layout, Sort};
+
+    fn round_trip(config: &Config) -> Config {
+        let written = toml::to_string(config).expect("the config can be written as toml");
+        toml::from_str(&written).expect("the written config reads back")
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let config = round_trip(&Config::default());
+        assert_eq!(config.theme, theme::DEFAULT_THEME);
+        assert!(config.views.is_empty());
+    }
+
+    #[test]
+    fn filled_in_config_round_trips() {
+        let config = Config {
+            todoist_key: "key".to_string(),
+            base_url: Some("http://localhost:8765/rest/v1".to_string()),
+            sync_url: Some("http://localhost:8765/sync/v8/sync".to_string()),
+            theme: "noir".to_string(),
+            filter_lang: Some("de".to_string()),
+            retry: Retry { max_retries: 5, ..Retry::default() },
+            views: vec![
+                SavedView {
+                    name: "Work".to_string(),
+                    filter: "#Work & p1".to_string(),
+                    sort: Sort::Due,
+                    group: Group::Label,
+                    layout: Layout::Board,
+                },
+                SavedView::query("today"),
+            ],
+        };
+
+        let read = round_trip(&config);
+        assert_eq!(read.todoist_key, "key");
+        assert_eq!(read.base_url, config.base_url);
+        assert_eq!(read.sync_url, config.sync_url);
+        assert_eq!(read.theme, "noir");
+        assert_eq!(read.filter_lang.as_deref(), Some("de"));
+        assert_eq!(read.retry.max_retries, 5);
+        assert_eq!(read.views.len(), 2);
+        assert_eq!(read.views[0].name, "Work");
+        assert_eq!(read.views[0].filter, "#Work & p1");
+        assert_eq!((read.views[0].sort, read.views[0].group, read.views[0].layout), (Sort::Due, Group::Label, Layout::Board));
+        assert_eq!(read.views[1].filter, "today");
+    }
 }
@@ -0,0 +1,437 @@
+// ##### Offline Conflicts #####
+
+// These are the functions that notice
+// when a task edited offline was also
+// changed on Todoist in the meantime.
+// Each field is compared against the
+// copy of the task from the last sync,
+// so only real clashes need a decision.
+// Moving a task to another section is
+// checked the same way as an edit.
+
+use std::io::Write;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde_json::Value;
+
+use crate::cache::{Mutation, Outbox};
+use crate::error::CotoError;
+use crate::model::{Due, Task};
+use crate::query::TodoistClient;
+use crate::sync::Store;
+
+/// The task fields that are checked for conflicts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Content,
+    Priority,
+    Due,
+    Section,
+}
+
+/// How to settle a conflict
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    /// Keep what Todoist has for every clashing field
+    Server,
+    /// Send the offline edit as it is
+    Local,
+    /// Stop and ask, field by field
+    Manual,
+}
+
+/// One field changed both offline and on Todoist since the last sync
+#[derive(Clone, Debug)]
+pub struct FieldConflict {
+    pub field: Field,
+    pub base: Value,
+    pub local: Value,
+    pub server: Value,
+}
+
+/// A queued task edit that clashes with the task as it is on Todoist now
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub server: Task,
+    pub fields: Vec<FieldConflict>,
+}
+
+impl Field {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Content => "content",
+            Field::Priority => "priority",
+            Field::Due => "due",
+            Field::Section => "section",
+        }
+    }
+
+    /// The keys in an update's args that set this field
+    fn arg_keys(&self) -> &'static [&'static str] {
+        match self {
+            Field::Content => &["content"],
+            Field::Priority => &["priority"],
+            Field::Due => &["due_string", "due_date", "due_datetime", "due_lang"],
+            Field::Section => &["section_id"],
+        }
+    }
+
+    /// This field of a task, as it is compared
+    fn of(&self, task: &Task) -> Value {
+        match self {
+            Field::Content => Value::from(task.content.as_str()),
+            Field::Priority => Value::from(task.priority),
+            Field::Due => match &task.due {
+                Some(due) => Value::from(due_when(due)),
+                None => Value::Null,
+            },
+            Field::Section => Value::from(task.section_id),
+        }
+    }
+
+    /// What an edit's args set this field to, if they touch it at all
+    fn in_args(&self, args: &Value) -> Option<Value> {
+        let value = self.arg_keys().iter()
+            .map(|key| &args[*key])
+            .find(|value| !value.is_null());
+
+        // A move to a project alone leaves every section
+        let value = match (self, value) {
+            (Field::Section, None) if !args["project_id"].is_null() => return Some(Value::from(0)),
+            (_, value) => value?,
+        };
+
+        // An empty due date clears it
+        match value.as_str() {
+            Some("") => Some(Value::Null),
+            _ => Some(value.clone()),
+        }
+    }
+}
+
+/// When a task is due, the same however the API it came from wrote it.
+/// The Sync API gives a floating due time in local time, REST always
+/// gives it in UTC, so both are read as an instant and shown locally.
+fn due_when(due: &Due) -> String {
+    let datetime = match &due.datetime {
+        Some(datetime) => datetime,
+        None => return due.date.clone(),
+    };
+
+    let local = match DateTime::parse_from_rfc3339(datetime) {
+        Ok(instant) => instant.with_timezone(&Local).naive_local(),
+        Err(_) => match NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S") {
+            Ok(floating) => floating,
+            Err(_) => return datetime.clone(),
+        },
+    };
+    local.format("%Y-%m-%d %H:%M").to_string()
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "server" => Ok(ConflictPolicy::Server),
+            "local" => Ok(ConflictPolicy::Local),
+            "manual" => Ok(ConflictPolicy::Manual),
+            other => Err(format!("Unknown conflict policy `{}` (use server, local or manual)", other)),
+        }
+    }
+}
+
+/// Compare an offline edit or move with the task on Todoist, given the task as last synced
+pub fn detect(base: &Task, args: &Value, server: &Task) -> Option<Conflict> {
+    let fields: Vec<FieldConflict> = [Field::Content, Field::Priority, Field::Due, Field::Section].iter()
+        .filter_map(|field| {
+            let local = field.in_args(args)?;
+            let base_value = field.of(base);
+            let server_value = field.of(server);
+
+            if server_value != base_value && server_value != local {
+                Some(FieldConflict {
+                    field: *field,
+                    base: base_value,
+                    local,
+                    server: server_value,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if fields.is_empty() {
+        None
+    } else {
+        Some(Conflict { server: server.clone(), fields })
+    }
+}
+
+/// Settle a conflict on a queued edit, keeping the offline value only for
+/// `keep_local` fields. The edit's base becomes the server copy, so it is
+/// not flagged again unless Todoist changes once more. Returns false when
+/// nothing is left to send.
+pub fn resolve(mutation: &mut Mutation, conflict: &Conflict, keep_local: &[Field]) -> bool {
+    if let Some((_, args, base)) = mutation.task_edit_mut() {
+        for field_conflict in conflict.fields.iter() {
+            if !keep_local.contains(&field_conflict.field) {
+                if let Some(args) = args.as_object_mut() {
+                    for key in field_conflict.field.arg_keys() {
+                        args.remove(*key);
+                    }
+                    if field_conflict.field == Field::Section {
+                        args.remove("project_id");
+                    }
+                }
+            }
+        }
+        *base = Some(Box::new(conflict.server.clone()));
+
+        return args.as_object().is_none_or(|args| !args.is_empty());
+    }
+    true
+}
+
+/// Settle a conflict on the oldest queued change with `policy`, or with
+/// `keep_local` for a manual merge, dropping it if nothing is left to send
+pub fn settle(outbox: &mut Outbox, conflict: &Conflict, policy: ConflictPolicy, keep_local: &[Field]) -> Result<(), CotoError> {
+    let every_field: Vec<Field> = conflict.fields.iter().map(|f| f.field).collect();
+    let keep = match policy {
+        ConflictPolicy::Server => &[][..],
+        ConflictPolicy::Local => &every_field[..],
+        ConflictPolicy::Manual => keep_local,
+    };
+
+    if let Some(queued) = outbox.queue.first_mut() {
+        if !resolve(&mut queued.mutation, conflict, keep) {
+            outbox.queue.remove(0);
+        }
+    }
+    outbox.save()
+}
+
+/// Replay the outbox from the command line, asking on stdin for a manual merge
+pub fn replay_from_cli(client: &TodoistClient, outbox: &mut Outbox, store: &mut Store, policy: ConflictPolicy) -> Result<(), CotoError> {
+    let mut runtime = tokio::runtime::Runtime::new().expect("Could not start runtime");
+    let mut sent = 0;
+
+    loop {
+        let report = runtime.block_on(outbox.flush(client, store, policy));
+        sent += report.sent;
+        for (queued, e) in report.rejected.iter() {
+            println!("Dropped {:?}: {}", queued.mutation, e);
+        }
+        if let Some(e) = report.stopped {
+            store.save()?;
+            return Err(e);
+        }
+
+        match report.conflict {
+            Some(conflict) => {
+                println!("\n\"{}\" was also changed on Todoist:", conflict.server.content);
+                let mut keep_local = Vec::new();
+                for field_conflict in conflict.fields.iter() {
+                    println!("  {}: was {}, yours {}, Todoist's {}",
+                        field_conflict.field.name(), field_conflict.base,
+                        field_conflict.local, field_conflict.server);
+                    if ask("  Keep [m]ine or [t]odoist's? ", &["m", "t"])? == "m" {
+                        keep_local.push(field_conflict.field);
+                    }
+                }
+                settle(outbox, &conflict, ConflictPolicy::Manual, &keep_local)?;
+            }
+            None => break,
+        }
+    }
+
+    store.save()?;
+    println!("Sent {} queued change(s), {} still queued", sent, outbox.len());
+    Ok(())
+}
+
+/// Ask until one of `answers` is given. Running out of input is an error,
+/// as nobody is there to answer (e.g. under cron or with `< /dev/null`).
+fn ask(prompt: &str, answers: &[&str]) -> Result<String, CotoError> {
+    loop {
+        print!("{}", prompt);
+        std::io::stdout().flush().unwrap();
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            println!();
+            return Err(CotoError::Usage(
+                "No answer to merge the conflict with, use --resolve server or --resolve local to settle it without asking".to_string()));
+        }
+        let answer = answer.trim().to_lowercase();
+        if answers.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::test_support::{date, due, due_at, section, task};
+
+    /// How REST writes a local due time: in UTC
+    fn rest_datetime(day: &str, hour: u32) -> String {
+        let local = date(day).and_hms_opt(hour, 0, 0).unwrap();
+        Local.from_local_datetime(&local).unwrap()
+            .with_timezone(&Utc)
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string()
+    }
+
+    fn update(args: Value, base: &Task) -> Mutation {
+        Mutation::UpdateTask { id: 1, args, base: Some(Box::new(base.clone())) }
+    }
+
+    fn fields(conflict: &Conflict) -> Vec<Field> {
+        conflict.fields.iter().map(|f| f.field).collect()
+    }
+
+    #[test]
+    fn no_conflict_when_todoist_has_not_changed() {
+        let base = task(1, 10, "Buy milk");
+        assert!(detect(&base, &json!({"content": "Buy oat milk"}), &base).is_none());
+    }
+
+    #[test]
+    fn same_field_changed_on_both_sides() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy soy milk");
+        let conflict = detect(&base, &json!({"content": "Buy oat milk"}), &server).unwrap();
+
+        assert_eq!(fields(&conflict), vec![Field::Content]);
+        assert_eq!(conflict.fields[0].base, json!("Buy milk"));
+        assert_eq!(conflict.fields[0].local, json!("Buy oat milk"));
+        assert_eq!(conflict.fields[0].server, json!("Buy soy milk"));
+    }
+
+    #[test]
+    fn no_conflict_when_both_sides_agree() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy oat milk");
+        assert!(detect(&base, &json!({"content": "Buy oat milk"}), &server).is_none());
+    }
+
+    #[test]
+    fn fields_left_alone_offline_do_not_conflict() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy soy milk");
+        assert!(detect(&base, &json!({"priority": 4}), &server).is_none());
+    }
+
+    #[test]
+    fn floating_due_time_matches_the_same_time_in_utc() {
+        let base = due_at(task(1, 10, "Call"), "2026-10-20", "2026-10-20T17:00:00");
+        let server = due_at(task(1, 10, "Call"), "2026-10-20", &rest_datetime("2026-10-20", 17));
+        assert!(detect(&base, &json!({"due_string": "tomorrow"}), &server).is_none());
+    }
+
+    #[test]
+    fn due_time_moved_on_todoist_conflicts() {
+        let base = due_at(task(1, 10, "Call"), "2026-10-20", "2026-10-20T17:00:00");
+        let server = due_at(task(1, 10, "Call"), "2026-10-20", &rest_datetime("2026-10-20", 18));
+        let conflict = detect(&base, &json!({"due_string": "tomorrow"}), &server).unwrap();
+
+        assert_eq!(fields(&conflict), vec![Field::Due]);
+        assert_eq!(conflict.fields[0].base, json!("2026-10-20 17:00"));
+        assert_eq!(conflict.fields[0].server, json!("2026-10-20 18:00"));
+    }
+
+    #[test]
+    fn all_day_due_dates_compare_by_date() {
+        let base = due(task(1, 10, "Call"), "2026-10-20");
+        assert!(detect(&base, &json!({"due_string": "friday"}), &base.clone()).is_none());
+
+        let server = due(task(1, 10, "Call"), "2026-10-21");
+        let conflict = detect(&base, &json!({"due_string": "friday"}), &server).unwrap();
+        assert_eq!(conflict.fields[0].server, json!("2026-10-21"));
+    }
+
+    #[test]
+    fn clearing_the_due_date_offline_conflicts_with_a_new_one() {
+        let base = due(task(1, 10, "Call"), "2026-10-20");
+        let server = due(task(1, 10, "Call"), "2026-10-22");
+        let conflict = detect(&base, &json!({"due_string": ""}), &server).unwrap();
+        assert_eq!(conflict.fields[0].local, Value::Null);
+    }
+
+    #[test]
+    fn moves_to_different_sections_conflict() {
+        let base = section(task(1, 10, "Draft"), 3);
+        let server = section(task(1, 10, "Draft"), 7);
+        let conflict = detect(&base, &json!({"section_id": 5}), &server).unwrap();
+
+        assert_eq!(fields(&conflict), vec![Field::Section]);
+        assert_eq!(conflict.fields[0].local, json!(5));
+    }
+
+    #[test]
+    fn move_to_the_project_leaves_every_section() {
+        let base = section(task(1, 10, "Draft"), 3);
+        let args = json!({"project_id": 10});
+
+        let conflict = detect(&base, &args, &section(task(1, 10, "Draft"), 7)).unwrap();
+        assert_eq!(conflict.fields[0].local, json!(0));
+        assert!(detect(&base, &args, &section(task(1, 10, "Draft"), 0)).is_none());
+    }
+
+    #[test]
+    fn resolving_for_todoist_drops_only_the_clashing_field() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy soy milk");
+        let mut mutation = update(json!({"content": "Buy oat milk", "priority": 4}), &base);
+        let conflict = detect(&base, &json!({"content": "Buy oat milk"}), &server).unwrap();
+
+        assert!(resolve(&mut mutation, &conflict, &[]));
+        let (_, args, new_base) = mutation.task_edit().unwrap();
+        assert_eq!(args, &json!({"priority": 4}));
+        assert_eq!(new_base.as_deref(), Some(&server));
+    }
+
+    #[test]
+    fn resolving_for_mine_keeps_the_edit() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy soy milk");
+        let args = json!({"content": "Buy oat milk"});
+        let mut mutation = update(args.clone(), &base);
+        let conflict = detect(&base, &args, &server).unwrap();
+
+        assert!(resolve(&mut mutation, &conflict, &[Field::Content]));
+        let (_, kept, new_base) = mutation.task_edit().unwrap();
+        assert_eq!(kept, &args);
+        // Judged against Todoist's copy from now on, so it is not flagged again
+        assert!(detect(new_base.as_ref().unwrap(), kept, &server).is_none());
+    }
+
+    #[test]
+    fn resolving_away_every_field_leaves_nothing_to_send() {
+        let base = task(1, 10, "Buy milk");
+        let server = task(1, 10, "Buy soy milk");
+        let args = json!({"content": "Buy oat milk"});
+        let mut mutation = update(args.clone(), &base);
+        let conflict = detect(&base, &args, &server).unwrap();
+
+        assert!(!resolve(&mut mutation, &conflict, &[]));
+    }
+
+    #[test]
+    fn resolving_a_move_for_todoist_drops_it() {
+        let base = section(task(1, 10, "Draft"), 3);
+        let server = section(task(1, 10, "Draft"), 7);
+        let args = json!({"project_id": 10});
+        let mut mutation = Mutation::MoveTask { id: 1, args: args.clone(), base: Some(Box::new(base.clone())) };
+        let conflict = detect(&base, &args, &server).unwrap();
+
+        assert!(!resolve(&mut mutation, &conflict, &[]));
+    }
+}
@@ -0,0 +1,115 @@
+// ##### Error Type #####
+
+// This is the error that every query
+// and config function hands back, so
+// that callers can tell an expired key
+// apart from a dropped connection.
+
+use std::fmt;
+
+use reqwest::StatusCode;
+
+#[derive(Debug)]
+pub enum CotoError {
+    /// The API key was missing, invalid or revoked (401/403)
+    Auth,
+    /// The project, section or task does not exist (404)
+    NotFound,
+    /// Too many requests (429), with the seconds to wait if Todoist said
+    RateLimited { retry_after: Option<u64> },
+    /// Todoist failed to handle the request (5xx)
+    Server { status: u16 },
+    /// Any other rejected request, with the body Todoist sent back
+    Api { status: u16, message: String },
+    /// The request never got a response
+    Network(reqwest::Error),
+    /// The response was not the JSON we expected
+    Decode(serde_json::Error),
+    /// The config file could not be read or written
+    Config(confy::ConfyError),
+    /// The local cache or outbox could not be read or written
+    Io(std::io::Error),
+    /// A command was given arguments that don't make sense
+    Usage(String),
+    /// A theme file is not valid toml or sets a bad color
+    Theme { name: String, message: String },
+    /// A filter query that coto could not read to run it locally
+    Filter(String),
+    /// A request running in the background panicked instead of finishing
+    Crashed(String),
+}
+
+impl CotoError {
+    /// Map an unsuccessful HTTP status to the matching error
+    pub fn from_status(status: StatusCode, retry_after: Option<u64>, body: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => CotoError::Auth,
+            404 => CotoError::NotFound,
+            429 => CotoError::RateLimited { retry_after },
+            500..=599 => CotoError::Server { status: status.as_u16() },
+            code => CotoError::Api { status: code, message: body },
+        }
+    }
+
+    /// Whether the same request could work if it were simply sent again
+    pub fn is_transient(&self) -> bool {
+        matches!(self, CotoError::Network(_) | CotoError::RateLimited { .. } | CotoError::Server { .. })
+    }
+}
+
+impl fmt::Display for CotoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CotoError::Auth => write!(f, "Todoist rejected the API key (run `coto --remove` to enter a new one)"),
+            CotoError::NotFound => write!(f, "That item no longer exists on Todoist"),
+            CotoError::RateLimited { retry_after: Some(secs) } => write!(f, "Rate limited by Todoist, try again in {} seconds", secs),
+            CotoError::RateLimited { retry_after: None } => write!(f, "Rate limited by Todoist, try again shortly"),
+            CotoError::Server { status } => write!(f, "Todoist had a server error ({})", status),
+            CotoError::Api { status, message } => write!(f, "Todoist refused the request ({}): {}", status, message),
+            CotoError::Network(e) => write!(f, "Could not reach Todoist: {}", e),
+            CotoError::Decode(e) => write!(f, "Could not understand Todoist's response: {}", e),
+            CotoError::Config(e) => write!(f, "Could not access the config file: {}", e),
+            CotoError::Io(e) => write!(f, "Could not access the local cache: {}", e),
+            CotoError::Usage(message) => write!(f, "{}", message),
+            CotoError::Theme { name, message } => write!(f, "Theme `{}`: {}", name, message),
+            CotoError::Filter(message) => write!(f, "Could not read the filter: {}", message),
+            CotoError::Crashed(message) => write!(f, "A background request crashed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CotoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CotoError::Network(e) => Some(e),
+            CotoError::Decode(e) => Some(e),
+            CotoError::Config(e) => Some(e),
+            CotoError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for CotoError {
+    fn from(e: reqwest::Error) -> Self {
+        CotoError::Network(e)
+    }
+}
+
+impl From<serde_json::Error> for CotoError {
+    fn from(e: serde_json::Error) -> Self {
+        CotoError::Decode(e)
+    }
+}
+
+impl From<confy::ConfyError> for CotoError {
+    fn from(e: confy::ConfyError) -> Self {
+        CotoError::Config(e)
+    }
+}
+
+impl From<std::io::Error> for CotoError {
+    fn from(e: std::io::Error) -> Self {
+        CotoError::Io(e)
+    }
+}
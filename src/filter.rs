@@ -0,0 +1,625 @@
+// ##### Local Filters #####
+
+// This is a parser and evaluator for the
+// Todoist filter language, so that queries
+// like `(today | overdue) & #Work & p1`
+// still work from the local cache when
+// Todoist can't be reached. Only English
+// keywords are understood, and due dates
+// are compared by day.
+
+use chrono::NaiveDate;
+
+use crate::agenda;
+use crate::error::CotoError;
+use crate::model::{Project, Label, Task};
+use crate::sync::Store;
+
+/// A parsed filter query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    /// `#name`, tasks directly in the project
+    Project(String),
+    /// `##name`, tasks in the project or any of its subprojects
+    ProjectTree(String),
+    /// `@name`
+    Label(String),
+    /// `p1` to `p4`, stored the way the API counts, so `p1` is 4
+    Priority(i64),
+    Today,
+    Overdue,
+    NoDate,
+    /// `due before: <day>`, due strictly earlier than the day
+    DueBefore(Day),
+    /// `search: <text>`, tasks whose content contains the text
+    Search(String),
+}
+
+/// A day in a filter, either a date or one relative to today
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Day {
+    Date(NaiveDate),
+    /// Days from today, so tomorrow is 1
+    FromToday(i64),
+}
+
+/// What a task is checked against besides its own fields
+pub struct Context<'a> {
+    pub today: NaiveDate,
+    pub projects: &'a [Project],
+    pub labels: &'a [Label],
+}
+
+/// Parse a filter query, or say what in it could not be understood
+pub fn parse(query: &str) -> Result<Filter, CotoError> {
+    let tokens = tokenize(query);
+    if tokens.is_empty() {
+        return Err(filter_error("the query is empty"));
+    }
+
+    let mut parser = Parser { tokens, position: 0 };
+    let filter = parser.or()?;
+    match parser.peek() {
+        None => Ok(filter),
+        Some(token) => Err(filter_error(&format!("unexpected {}", token.describe()))),
+    }
+}
+
+/// Run `query` against every task in `store`
+pub fn run(query: &str, store: &Store, today: NaiveDate) -> Result<Vec<Task>, CotoError> {
+    let filter = parse(query)?;
+    let projects = store.projects();
+    let labels = store.labels();
+    let context = Context { today, projects: &projects, labels: &labels };
+    Ok(apply(&filter, &store.tasks(), &context))
+}
+
+/// The tasks in `tasks` that `filter` matches, in the order given
+pub fn apply(filter: &Filter, tasks: &[Task], context: &Context) -> Vec<Task> {
+    tasks.iter()
+        .filter(|task| filter.matches(task, context))
+        .cloned()
+        .collect()
+}
+
+impl Filter {
+    pub fn matches(&self, task: &Task, context: &Context) -> bool {
+        match self {
+            Filter::And(left, right) => left.matches(task, context) && right.matches(task, context),
+            Filter::Or(left, right) => left.matches(task, context) || right.matches(task, context),
+            Filter::Not(filter) => !filter.matches(task, context),
+            Filter::Project(name) => project(context, task.project_id)
+                .is_some_and(|project| same_name(&project.name, name)),
+            Filter::ProjectTree(name) => in_project_tree(context, task.project_id, name),
+            Filter::Label(name) => context.labels.iter()
+                .any(|label| task.label_ids.contains(&label.id) && same_name(&label.name, name)),
+            Filter::Priority(priority) => task.priority == *priority,
+            Filter::Today => agenda::due_date(task) == Some(context.today),
+            Filter::Overdue => agenda::due_date(task).is_some_and(|date| date < context.today),
+            Filter::NoDate => task.due.is_none(),
+            Filter::DueBefore(day) => agenda::due_date(task).is_some_and(|date| date < day.resolve(context.today)),
+            Filter::Search(text) => task.content.to_lowercase().contains(&text.to_lowercase()),
+        }
+    }
+}
+
+impl Day {
+    fn resolve(self, today: NaiveDate) -> NaiveDate {
+        match self {
+            Day::Date(date) => date,
+            Day::FromToday(days) => today + chrono::Duration::days(days),
+        }
+    }
+}
+
+fn project<'a>(context: &Context<'a>, id: i64) -> Option<&'a Project> {
+    context.projects.iter().find(|project| project.id == id)
+}
+
+/// Whether the project `id` is called `name`, or sits somewhere under one that is
+fn in_project_tree(context: &Context, id: i64, name: &str) -> bool {
+    let mut current = project(context, id);
+    // Bounded by the number of projects, in case the parents ever loop
+    for _ in 0..=context.projects.len() {
+        match current {
+            Some(project) if same_name(&project.name, name) => return true,
+            Some(project) => current = project.parent_id.and_then(|parent| self::project(context, parent)),
+            None => return false,
+        }
+    }
+    false
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+fn filter_error(message: &str) -> CotoError {
+    CotoError::Filter(message.to_string())
+}
+
+// ##### Parsing #####
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    /// Everything between two operators, e.g. `#Work` or `due before: 2020-05-05`
+    Term(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::And => "`&`".to_string(),
+            Token::Or => "`|`".to_string(),
+            Token::Not => "`!`".to_string(),
+            Token::Open => "`(`".to_string(),
+            Token::Close => "`)`".to_string(),
+            Token::Term(term) => format!("`{}`", term),
+        }
+    }
+}
+
+/// Split a query at its operators. A term runs up to the next `&`, `|`,
+/// `(` or `)`, so names and dates may hold spaces. `!` is only an operator
+/// where a term would start.
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => {
+                let mut term = String::new();
+                while let Some(&c) = chars.peek() {
+                    if "&|()".contains(c) {
+                        break;
+                    }
+                    term.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Term(term.trim().to_string()));
+                continue;
+            }
+        };
+        chars.next();
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Recursive descent, with `!` binding tightest, then `&`, then `|`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Filter, CotoError> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, CotoError> {
+        let mut filter = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, CotoError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Filter, CotoError> {
+        match self.next() {
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    Some(token) => Err(filter_error(&format!("expected `)` but found {}", token.describe()))),
+                    None => Err(filter_error("a `(` is never closed")),
+                }
+            }
+            Some(Token::Term(term)) => term_filter(&term),
+            Some(token) => Err(filter_error(&format!("expected a filter but found {}", token.describe()))),
+            None => Err(filter_error("the query ends where a filter was expected")),
+        }
+    }
+}
+
+/// What a single term asks for
+fn term_filter(term: &str) -> Result<Filter, CotoError> {
+    let lower = term.to_lowercase();
+    if let Some(name) = term.strip_prefix("##") {
+        return Ok(Filter::ProjectTree(name_in(term, name)?));
+    }
+    if let Some(name) = term.strip_prefix('#') {
+        return Ok(Filter::Project(name_in(term, name)?));
+    }
+    if let Some(name) = term.strip_prefix('@') {
+        return Ok(Filter::Label(name_in(term, name)?));
+    }
+    if let Some(day) = lower.strip_prefix("due before:") {
+        return Ok(Filter::DueBefore(parse_day(day.trim())?));
+    }
+    if lower.starts_with("search:") {
+        let text = term["search:".len()..].trim();
+        if text.is_empty() {
+            return Err(filter_error("`search:` needs some text to look for"));
+        }
+        return Ok(Filter::Search(text.to_string()));
+    }
+
+    match lower.as_str() {
+        "today" => Ok(Filter::Today),
+        "overdue" | "od" => Ok(Filter::Overdue),
+        "no date" | "no due date" => Ok(Filter::NoDate),
+        "p1" => Ok(Filter::Priority(4)),
+        "p2" => Ok(Filter::Priority(3)),
+        "p3" => Ok(Filter::Priority(2)),
+        "p4" => Ok(Filter::Priority(1)),
+        _ => Err(filter_error(&format!("`{}` is not a filter coto knows", term))),
+    }
+}
+
+fn name_in(term: &str, name: &str) -> Result<String, CotoError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(filter_error(&format!("`{}` needs a name after it", term)));
+    }
+    Ok(name.to_string())
+}
+
+fn parse_day(day: &str) -> Result<Day, CotoError> {
+    match day {
+        "today" => Ok(Day::FromToday(0)),
+        "tomorrow" => Ok(Day::FromToday(1)),
+        "yesterday" => Ok(Day::FromToday(-1)),
+        _ => NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map(Day::Date)
+            .map_err(|_| filter_error(&format!(
+                "can't read the date `{}`, use YYYY-MM-DD, today, tomorrow or yesterday", day))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{date, due, label, labelled, priority, project, task};
+
+    /// Work has a Clients subproject, which has its own Acme subproject
+    fn projects() -> Vec<Project> {
+        vec![
+            project(1, "Inbox", None),
+            project(2, "Work", None),
+            project(3, "Clients", Some(2)),
+            project(4, "Acme", Some(3)),
+            project(5, "Home", None),
+        ]
+    }
+
+    fn labels() -> Vec<Label> {
+        vec![label(7, "waiting"), label(8, "Errands")]
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            priority(due(task(100, 2, "Write report"), "2026-10-18"), 4),
+            due(task(101, 1, "Buy milk"), "2026-10-15"),
+            labelled(due(task(102, 3, "Call client back"), "2026-10-20"), &[7]),
+            priority(labelled(task(103, 4, "Send Acme invoice"), &[7, 8]), 3),
+            priority(task(104, 5, "Fix the sink"), 2),
+            due(task(105, 5, "Renew passport"), "2026-10-28"),
+        ]
+    }
+
+    /// The ids of the sample tasks `query` picks out, with today on 2026-10-18
+    fn ids(query: &str) -> Vec<i64> {
+        let filter = parse(query).unwrap_or_else(|e| panic!("{}: {}", query, e));
+        let projects = projects();
+        let labels = labels();
+        let context = Context { today: date("2026-10-18"), projects: &projects, labels: &labels };
+        apply(&filter, &tasks(), &context).iter().map(|task| task.id).collect()
+    }
+
+    fn error(query: &str) -> String {
+        match parse(query) {
+            Ok(filter) => panic!("{} parsed as {:?}", query, filter),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn term(filter: Filter) -> Box<Filter> {
+        Box::new(filter)
+    }
+
+    // ##### Tokens #####
+
+    #[test]
+    fn tokenize_splits_at_operators() {
+        assert_eq!(tokenize("(today|overdue)&!p1"), vec![
+            Token::Open, Token::Term("today".to_string()), Token::Or, Token::Term("overdue".to_string()),
+            Token::Close, Token::And, Token::Not, Token::Term("p1".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_keeps_spaces_inside_terms() {
+        assert_eq!(tokenize("  #Shopping list &  due before: 2026-11-01 "), vec![
+            Token::Term("#Shopping list".to_string()), Token::And, Token::Term("due before: 2026-11-01".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_keeps_bang_inside_a_term() {
+        assert_eq!(tokenize("search: done!"), vec![Token::Term("search: done!".to_string())]);
+    }
+
+    #[test]
+    fn tokenize_empty_query() {
+        assert!(tokenize("   ").is_empty());
+    }
+
+    // ##### Parsing #####
+
+    #[test]
+    fn parse_every_term() {
+        assert_eq!(parse("#Work").unwrap(), Filter::Project("Work".to_string()));
+        assert_eq!(parse("##Work").unwrap(), Filter::ProjectTree("Work".to_string()));
+        assert_eq!(parse("@waiting").unwrap(), Filter::Label("waiting".to_string()));
+        assert_eq!(parse("p1").unwrap(), Filter::Priority(4));
+        assert_eq!(parse("p2").unwrap(), Filter::Priority(3));
+        assert_eq!(parse("p3").unwrap(), Filter::Priority(2));
+        assert_eq!(parse("p4").unwrap(), Filter::Priority(1));
+        assert_eq!(parse("today").unwrap(), Filter::Today);
+        assert_eq!(parse("overdue").unwrap(), Filter::Overdue);
+        assert_eq!(parse("od").unwrap(), Filter::Overdue);
+        assert_eq!(parse("no date").unwrap(), Filter::NoDate);
+        assert_eq!(parse("no due date").unwrap(), Filter::NoDate);
+        assert_eq!(parse("due before: 2026-11-01").unwrap(), Filter::DueBefore(Day::Date(date("2026-11-01"))));
+        assert_eq!(parse("due before: tomorrow").unwrap(), Filter::DueBefore(Day::FromToday(1)));
+        assert_eq!(parse("search: report").unwrap(), Filter::Search("report".to_string()));
+    }
+
+    #[test]
+    fn parse_keywords_ignore_case() {
+        assert_eq!(parse("TODAY").unwrap(), Filter::Today);
+        assert_eq!(parse("No Date").unwrap(), Filter::NoDate);
+        assert_eq!(parse("P1").unwrap(), Filter::Priority(4));
+        assert_eq!(parse("Due Before: Yesterday").unwrap(), Filter::DueBefore(Day::FromToday(-1)));
+        assert_eq!(parse("Search: Report").unwrap(), Filter::Search("Report".to_string()));
+    }
+
+    #[test]
+    fn parse_and_binds_tighter_than_or() {
+        assert_eq!(parse("today | overdue & p1").unwrap(), Filter::Or(
+            term(Filter::Today),
+            term(Filter::And(term(Filter::Overdue), term(Filter::Priority(4)))),
+        ));
+    }
+
+    #[test]
+    fn parse_not_binds_tightest() {
+        assert_eq!(parse("!today & p1").unwrap(), Filter::And(
+            term(Filter::Not(term(Filter::Today))),
+            term(Filter::Priority(4)),
+        ));
+        assert_eq!(parse("!!today").unwrap(), Filter::Not(term(Filter::Not(term(Filter::Today)))));
+    }
+
+    #[test]
+    fn parse_parentheses_group() {
+        assert_eq!(parse("(today | overdue) & #Work").unwrap(), Filter::And(
+            term(Filter::Or(term(Filter::Today), term(Filter::Overdue))),
+            term(Filter::Project("Work".to_string())),
+        ));
+        assert_eq!(parse("!(p1 | p2)").unwrap(), Filter::Not(
+            term(Filter::Or(term(Filter::Priority(4)), term(Filter::Priority(3)))),
+        ));
+        assert_eq!(parse("((today))").unwrap(), Filter::Today);
+    }
+
+    #[test]
+    fn parse_chains_are_left_associative() {
+        assert_eq!(parse("p1 | p2 | p3").unwrap(), Filter::Or(
+            term(Filter::Or(term(Filter::Priority(4)), term(Filter::Priority(3)))),
+            term(Filter::Priority(2)),
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_empty_queries() {
+        assert!(error("").contains("empty"));
+        assert!(error("  ").contains("empty"));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_terms() {
+        assert!(error("someday").contains("`someday` is not a filter"));
+        assert!(error("today & p5").contains("`p5`"));
+    }
+
+    #[test]
+    fn parse_rejects_missing_names() {
+        assert!(error("#").contains("needs a name"));
+        assert!(error("## ").contains("needs a name"));
+        assert!(error("@ & today").contains("needs a name"));
+        assert!(error("search:").contains("needs some text"));
+    }
+
+    #[test]
+    fn parse_rejects_bad_dates() {
+        assert!(error("due before: next week").contains("can't read the date `next week`"));
+        assert!(error("due before: 2026-13-01").contains("can't read the date"));
+    }
+
+    #[test]
+    fn parse_rejects_unbalanced_parentheses() {
+        assert!(error("(today | overdue").contains("never closed"));
+        assert!(error("today)").contains("unexpected `)`"));
+        assert!(error("()").contains("expected a filter but found `)`"));
+        assert!(error("(today #Work").contains("`today #Work` is not a filter"));
+    }
+
+    #[test]
+    fn parse_rejects_dangling_operators() {
+        assert!(error("today &").contains("ends where a filter was expected"));
+        assert!(error("| today").contains("expected a filter but found `|`"));
+        assert!(error("today & | p1").contains("found `|`"));
+        assert!(error("!").contains("ends where a filter was expected"));
+    }
+
+    #[test]
+    fn parse_errors_are_filter_errors() {
+        assert!(matches!(parse("nonsense"), Err(CotoError::Filter(_))));
+    }
+
+    // ##### Evaluation #####
+
+    #[test]
+    fn project_matches_only_that_project() {
+        assert_eq!(ids("#Work"), vec![100]);
+        assert_eq!(ids("#Home"), vec![104, 105]);
+        assert_eq!(ids("#Nowhere"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn project_names_ignore_case() {
+        assert_eq!(ids("#work"), vec![100]);
+        assert_eq!(ids("#INBOX"), vec![101]);
+    }
+
+    #[test]
+    fn project_tree_includes_subprojects() {
+        assert_eq!(ids("##Work"), vec![100, 102, 103]);
+        assert_eq!(ids("##Clients"), vec![102, 103]);
+        assert_eq!(ids("##Acme"), vec![103]);
+    }
+
+    #[test]
+    fn project_tree_survives_a_parent_loop() {
+        let projects = vec![project(1, "A", Some(2)), project(2, "B", Some(1))];
+        let context = Context { today: date("2026-10-18"), projects: &projects, labels: &[] };
+        assert!(!parse("##C").unwrap().matches(&task(1, 1, "x"), &context));
+        assert!(parse("##B").unwrap().matches(&task(1, 1, "x"), &context));
+    }
+
+    #[test]
+    fn label_matches_any_of_the_tasks_labels() {
+        assert_eq!(ids("@waiting"), vec![102, 103]);
+        assert_eq!(ids("@errands"), vec![103]);
+        assert_eq!(ids("@unknown"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn priorities_count_from_p1_as_most_urgent() {
+        assert_eq!(ids("p1"), vec![100]);
+        assert_eq!(ids("p2"), vec![103]);
+        assert_eq!(ids("p3"), vec![104]);
+        assert_eq!(ids("p4"), vec![101, 102, 105]);
+    }
+
+    #[test]
+    fn today_and_overdue() {
+        assert_eq!(ids("today"), vec![100]);
+        assert_eq!(ids("overdue"), vec![101]);
+        assert_eq!(ids("today | overdue"), vec![100, 101]);
+    }
+
+    #[test]
+    fn no_date() {
+        assert_eq!(ids("no date"), vec![103, 104]);
+        assert_eq!(ids("!no date"), vec![100, 101, 102, 105]);
+    }
+
+    #[test]
+    fn due_before_is_exclusive() {
+        assert_eq!(ids("due before: 2026-10-20"), vec![100, 101]);
+        assert_eq!(ids("due before: 2026-10-21"), vec![100, 101, 102]);
+        assert_eq!(ids("due before: 2026-10-01"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn due_before_relative_days() {
+        assert_eq!(ids("due before: today"), vec![101]);
+        assert_eq!(ids("due before: tomorrow"), vec![100, 101]);
+        assert_eq!(ids("due before: yesterday"), vec![101]);
+    }
+
+    #[test]
+    fn search_looks_inside_content_ignoring_case() {
+        assert_eq!(ids("search: report"), vec![100]);
+        assert_eq!(ids("search: ACME"), vec![103]);
+        assert_eq!(ids("search: the sink"), vec![104]);
+        assert_eq!(ids("search: e"), vec![100, 102, 103, 104, 105]);
+        assert_eq!(ids("search: nothing like it"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn combinations() {
+        assert_eq!(ids("(today | overdue) & #Work & p1"), vec![100]);
+        assert_eq!(ids("##Work & @waiting"), vec![102, 103]);
+        assert_eq!(ids("##Work & !@waiting"), vec![100]);
+        assert_eq!(ids("no date | due before: 2026-10-16"), vec![101, 103, 104]);
+        assert_eq!(ids("!(p1 | p4)"), vec![103, 104]);
+        assert_eq!(ids("#Home & (p3 | search: passport)"), vec![104, 105]);
+        assert_eq!(ids("today & overdue"), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn apply_keeps_the_given_order() {
+        let projects = projects();
+        let labels = labels();
+        let context = Context { today: date("2026-10-18"), projects: &projects, labels: &labels };
+        let mut reversed = tasks();
+        reversed.reverse();
+        let found: Vec<i64> = apply(&parse("p4").unwrap(), &reversed, &context).iter().map(|task| task.id).collect();
+        assert_eq!(found, vec![105, 102, 101]);
+    }
+
+    #[test]
+    fn undated_tasks_never_match_date_filters() {
+        let projects = projects();
+        let context = Context { today: date("2026-10-18"), projects: &projects, labels: &[] };
+        let undated = task(1, 2, "x");
+        for query in ["today", "overdue", "due before: 2099-01-01"].iter() {
+            assert!(!parse(query).unwrap().matches(&undated, &context), "{}", query);
+        }
+    }
+}
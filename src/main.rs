@@ -1,88 +1,310 @@
-use serde_json::{json, Value};
+use std::cell::Cell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use signal_hook::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use serde_json::json;
 use tokio::runtime::Runtime;
+use tokio::task::JoinError;
+use uuid::Uuid;
 use structopt::StructOpt;
 use cursive::Cursive;
 use cursive::align::HAlign;
-use cursive::views::{Dialog, EditView, LinearLayout, SelectView,
-    TextView, OnEventView, TextArea, TextContent};
+use cursive::event::Key;
+use cursive::theme::{ColorStyle, Effect, Style};
+use cursive::utils::markup::StyledString;
+use cursive::views::{Checkbox, Dialog, DummyView, EditView, LinearLayout, NamedView, Panel, RadioGroup, SelectView,
+    TextView, OnEventView, TextArea};
 use cursive::traits::*;
 
+mod agenda;
+mod cache;
+mod cli;
 mod config;
+mod conflict;
+mod error;
+mod filter;
+mod model;
+mod output;
 mod query;
+mod search;
+mod sync;
+#[cfg(test)]
+mod test_support;
+mod theme;
+mod view;
+
+use cache::{FlushReport, Mutation, Outbox};
+use conflict::{Conflict, ConflictPolicy, Field};
+use error::CotoError;
+use model::{Project, Section, Task, Label, Comment, COLORS};
+use query::TodoistClient;
+use sync::Store;
+use view::{Layout, SavedView};
+
+/// What a thread of comments is attached to
+#[derive(Clone, Copy)]
+enum CommentTarget {
+    Task(i64),
+    Project(i64),
+}
+
+/// State shared by every screen, kept in the cursive user data
+struct App {
+    client: TodoistClient,
+    runtime: Runtime,
+    store: Store,
+    outbox: Outbox,
+    /// Set when the last sync could not reach Todoist
+    offline: bool,
+    /// Queued changes whose conflict was put off with "Later", which hold
+    /// up the outbox until coto is started again
+    deferred: Vec<Uuid>,
+    /// The theme saved in the config, which previews fall back to
+    theme: String,
+    /// The language filter queries are written in, if not English
+    filter_lang: Option<String>,
+    /// The views saved in the config, listed under the projects
+    views: Vec<SavedView>,
+    /// Requests still running in the background
+    loading: usize,
+    /// Set while the outbox is flushed and the store synced
+    syncing: bool,
+    /// Another sync was asked for while one was running
+    sync_again: bool,
+    screen: Option<Screen>,
+    /// What was typed in the search bar when the screen was last drawn,
+    /// for the redraw to filter by it again
+    search: Option<String>,
+}
+
+/// Something to do to the UI, shared between the callbacks that can do it
+type Action = Rc<dyn Fn(&mut Cursive)>;
+
+/// The screen last drawn, and how to draw it again when a sync brings news
+struct Screen {
+    /// How many layers were up when it was drawn. A sync only redraws it
+    /// while nothing has been opened over it or closed under it.
+    layers: usize,
+    redraw: Action,
+}
 
 #[derive(StructOpt)]
 struct Cli {
     /// Remove existing API key
     #[structopt(short, long)]
     remove: bool,
+
+    /// Send queued offline changes without starting the UI, settling
+    /// conflicts by keeping the server's or local edits, or asking (manual)
+    #[structopt(long, value_name = "server|local|manual")]
+    resolve: Option<ConflictPolicy>,
+
+    #[structopt(subcommand)]
+    command: Option<cli::Command>,
 }
 
-#[derive(Debug)]
-struct Project {
-    count: i32,
-    id: String,
-    name: String,
-    shared: String,
+fn main() {
+    let cli = Cli::from_args();
+    let (conf, mut outbox) = match setup(&cli) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(policy) = cli.resolve {
+        let client = TodoistClient::from_config(&conf);
+        let mut store = Store::load().unwrap_or_default();
+        if let Err(e) = conflict::replay_from_cli(&client, &mut outbox, &mut store, policy) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(command) = cli.command {
+        if let Err(e) = cli::run(&TodoistClient::from_config(&conf), &conf, &outbox, command) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Checked before the UI starts, so the error lands on a normal terminal
+    let runtime = match Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Could not start the background runtime: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let app = App {
+        client: TodoistClient::from_config(&conf),
+        runtime,
+        // A damaged cache is only a cache, the next sync rebuilds it
+        store: Store::load().unwrap_or_default(),
+        outbox,
+        offline: false,
+        deferred: Vec::new(),
+        theme: conf.theme.clone(),
+        filter_lang: conf.filter_lang.clone(),
+        views: conf.views.clone(),
+        loading: 0,
+        syncing: false,
+        sync_again: false,
+        screen: None,
+        search: None,
+    };
+
+    if let Err(crash) = run_ui(app) {
+        eprintln!("coto crashed: {}", crash);
+        std::process::exit(1);
+    }
 }
 
-struct Section {
-    id: String,
-    project_id: String,
-    order: i32,
-    real_order: String,
-    name: String,
+/// Run the UI until it quits. A panic is held back until the UI has been
+/// dropped, which puts the terminal back, and is then returned instead
+/// of being printed over the screen.
+fn run_ui(app: App) -> Result<(), String> {
+    let crash = Arc::new(Mutex::new(None));
+    let seen = crash.clone();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(mut seen) = seen.lock() {
+            *seen = Some(info.to_string());
+        }
+    }));
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let theme_name = app.theme.clone();
+        // Taken first, or ncurses puts in its own handlers that exit at once
+        let signals = Signals::new([SIGINT, SIGTERM, SIGHUP]).ok();
+        let mut ui = cursive::default();
+        ui.set_user_data(app);
+        if let Some(signals) = signals {
+            quit_on_signals(&mut ui, signals);
+        }
+
+        let theme_result = theme::apply(&mut ui, &theme_name);
+        if theme_result.is_err() {
+            theme::apply(&mut ui, theme::DEFAULT_THEME).expect("The default theme loads");
+        }
+        project_overview(&mut ui);
+        match theme_result {
+            Err(e) => show_error(&mut ui, e),
+            // Bundled themes are as they are, but a user theme can still be fixed
+            Ok(warnings) if !warnings.is_empty() && theme::is_user_theme(&theme_name) => {
+                ui.add_layer(Dialog::info(format!("Some text in theme `{}` may be hard to read:\n\n{}",
+                        theme_name, warnings.join("\n")))
+                    .title("Low contrast"));
+            }
+            Ok(_) => {}
+        }
+        ui.run();
+
+        // Dropping the runtime would wait on any lookup still stuck on a dead connection
+        if let Some(app) = ui.take_user_data::<App>() {
+            app.runtime.shutdown_timeout(Duration::from_millis(200));
+        }
+    }));
+
+    let _ = panic::take_hook();
+    match result {
+        Ok(()) => Ok(()),
+        Err(_) => Err(crash.lock().ok()
+            .and_then(|mut crash| crash.take())
+            .unwrap_or_else(|| "unknown panic".to_string())),
+    }
 }
 
-struct Task {
-    id: String,
-    section_id: String,
-    order: i32,
-    real_order: String,
-    content: String,
-    priority: String,
-    created: String,
-    due: String,
+/// Quit through cursive when coto is sent SIGINT, SIGTERM or SIGHUP, so
+/// the terminal is restored as on a normal exit. Ctrl+C typed in the UI
+/// arrives as a key press instead, which cursive already quits on.
+fn quit_on_signals(ui: &mut Cursive, signals: Signals) {
+    let sink = ui.cb_sink().clone();
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if sink.send(Box::new(|ui: &mut Cursive| ui.quit())).is_err() {
+                break;
+            }
+        }
+    });
 }
 
-fn main() {
+fn setup(cli: &Cli) -> Result<(config::Config, Outbox), CotoError> {
     // Config setup
-    if Cli::from_args().remove == true {
-        config::remove_key();
+    if cli.remove {
+        config::remove_key()?;
+    }
+    match &cli.command {
+        // Nothing to ask Todoist, so no key is needed
+        Some(command) if !command.needs_key() => {}
+        _ => config::config_setup(cli.command.is_none() && cli.resolve.is_none())?,
     }
-    config::config_setup();
 
-    let mut ui = cursive::default();
-    project_overview(&mut ui);
+    Ok((confy::load("coto")?, Outbox::load()?))
 }
 
 fn project_overview(ui: &mut Cursive) {
-    let project_vec = gather_projects().unwrap();
+    draw_projects(ui);
+    sync_in_background(ui);
+}
+
+fn draw_projects(ui: &mut Cursive) {
+    let project_vec = gather_projects(ui);
+    let query = app(ui).search.take();
 
     // Display the overview
     ui.pop_layer();
-    ui.load_toml(include_str!("../styles/original.toml")).unwrap();
 
-    let mut select = SelectView::new();
-    for project in project_vec.iter() {
-        select.add_item(project.name.to_string(), project.id.to_string());
+    let entries = project_vec.iter()
+        .map(|project| Entry { text: project.name.to_string(), suffix: String::new(), id: project.id })
+        .collect();
+    let mut search = Search::new();
+    let mut column = LinearLayout::vertical()
+        .child(search.list("projects", SelectView::new().on_submit(task_overview), entries,
+            query.as_deref().unwrap_or("")));
+
+    // Saved views are listed under the projects, by their place in the config
+    let view_entries: Vec<Entry> = app(ui).views.iter().enumerate()
+        .map(|(index, view)| Entry { text: view.name.to_string(), suffix: String::new(), id: index as i64 })
+        .collect();
+    if !view_entries.is_empty() {
+        let views = SelectView::new().on_submit(|ui, index: &i64| {
+            if let Some(view) = app(ui).views.get(*index as usize).cloned() {
+                run_view(ui, view);
+            }
+        });
+        column.add_child(TextView::new("\nViews"));
+        column.add_child(search.list("views", views, view_entries, query.as_deref().unwrap_or("")));
     }
-    select.set_on_submit(task_overview);
+    column.add_child(search_slot());
+    let search = Rc::new(search);
 
     // Display controls
-    let controls = "[C]reate project\n[D]elete project\n[U]pdate project\n[S]ettings\n[Q]uit";
+    let title = screen_title(ui, "Projects");
+    let controls = "[I]nbox\n[T]oday\nU[p]coming\n[F]ilter tasks\n[C]reate project\n[D]elete project\n[U]pdate project\n[L]abels\n[/] Search\n[S]ettings\n[Q]uit";
 
     // Control callbacks
-    let callbacks = OnEventView::new(select)
+    let open = search.clone();
+    let close = search.clone();
+    let next = search.clone();
+    let previous = search.clone();
+    let callbacks = OnEventView::new(column)
         // Create Project
         .on_event('c', |ui| {
             fn ok(ui: &mut Cursive, name: &str) {
-                let conf_call: config::Config = confy::load("coto").expect("Could not load config");
-                let key_call: String = conf_call.todoist_key;
-                let json_name = json!({"name": name}).to_string();
-                Runtime::new().expect("Could not create new project")
-                    .block_on(query::new_project(&key_call, json_name))
-                    .unwrap();
-                project_overview(ui);
+                let temp_id = app(ui).outbox.temp_id();
+                match mutate(ui, Mutation::NewProject { temp_id, args: json!({"name": name}) }) {
+                    Ok(_) => project_overview(ui),
+                    Err(e) => show_error(ui, e),
+                }
             }
 
             ui.pop_layer();
@@ -92,20 +314,18 @@ fn project_overview(ui: &mut Cursive) {
         })
         // Delete Project
         .on_event('d', |ui| {
-            fn ok(ui: &mut Cursive, project_id: &str) {
-                let conf_call: config::Config = confy::load("coto").expect("Could not load config");
-                let key_call: String = conf_call.todoist_key;
-
-                Runtime::new().expect("Could not delete project")
-                    .block_on(query::delete_project(&key_call, &project_id.to_string()))
-                    .unwrap();
-                project_overview(ui);
+            fn ok(ui: &mut Cursive, project_id: &i64) {
+                let project_id = *project_id;
+                match mutate(ui, Mutation::DeleteProject { id: project_id }) {
+                    Ok(_) => project_overview(ui),
+                    Err(e) => show_error(ui, e),
+                }
             }
 
             let mut sub_select = SelectView::new();
-            let projects = gather_projects().unwrap();
+            let projects = gather_projects(ui);
             for project in projects.iter() {
-                sub_select.add_item(project.name.to_string(), project.id.to_string());
+                sub_select.add_item(project.name.to_string(), project.id);
             }
             sub_select.set_on_submit(ok);
             let delete_callback = OnEventView::new(sub_select)
@@ -121,26 +341,12 @@ fn project_overview(ui: &mut Cursive) {
         })
         // Update Project
         .on_event('u', |ui| {
-            fn ok(ui: &mut Cursive, project_id: &str) {
-                let conf_call: config::Config = confy::load("coto").expect("Could not load config");
-                let key_call: String = conf_call.todoist_key;
-
-                let mut new_name: String = String::new();
-                // Just need to find a way to chain text entries, then the whole thing will come together
-
-                let json_name = json!({"name": &new_name}).to_string();
-                Runtime::new().expect("Could not update project")
-                    .block_on(query::update_project(&key_call, &project_id.to_string(), json_name))
-                    .unwrap();
-                project_overview(ui);
-            }
-
             let mut sub_select = SelectView::new();
-            let projects = gather_projects().unwrap();
-            for project in projects.iter() {
-                sub_select.add_item(project.name.to_string(), project.id.to_string());
+            let projects = gather_projects(ui);
+            for project in projects.into_iter() {
+                sub_select.add_item(project.name.to_string(), project);
             }
-            sub_select.set_on_submit(ok);
+            sub_select.set_on_submit(|ui, project: &Project| edit_project(ui, project, project_overview));
 
             let update_callback = OnEventView::new(sub_select)
                 .on_event('b', project_overview)
@@ -151,134 +357,1695 @@ fn project_overview(ui: &mut Cursive) {
                     .child(TextView::new("You can go [B]ack or [Q]uit if you want."))
                     .child(update_callback))
                 .title("Project to update"));
-
-
         })
-        .on_event('s', |ui| {})
+        .on_event('i', inbox)
+        .on_event('t', |ui| agenda_overview(ui, Agenda::Today))
+        .on_event('p', |ui| agenda_overview(ui, Agenda::Upcoming(agenda::UPCOMING_DAYS)))
+        .on_event('f', filter_prompt)
+        .on_event('l', label_manager)
+        .on_event('/', move |ui| open_search(ui, &open, ""))
+        .on_event('n', move |ui| step_result(ui, &next, true))
+        .on_event('N', move |ui| step_result(ui, &previous, false))
+        .on_event(Key::Esc, move |ui| close_search(ui, &close))
+        .on_event('s', settings)
         .on_event('q', |ui| ui.quit());
 
+    let status = status_view(ui);
     ui.add_layer(Dialog::around(LinearLayout::horizontal()
             .child(callbacks)
-            .child(TextView::new(controls)))
-        .title("Projects")
+            .child(DummyView.fixed_width(2))
+            .child(LinearLayout::vertical()
+                .child(TextView::new(controls))
+                .child(status)))
+        .title(title)
         .h_align(HAlign::Center));
+    if let Some(query) = query {
+        open_search(ui, &search, &query);
+    }
+    set_screen(ui, draw_projects);
+}
+
+/// Pick a theme, previewing each one as it is highlighted
+fn settings(ui: &mut Cursive) {
+    let current = app(ui).theme.clone();
+    let names = theme::names();
+
+    let mut themes = SelectView::new();
+    for name in names.iter() {
+        themes.add_item(name.to_string(), name.to_string());
+    }
+    let selected = names.iter().position(|name| *name == current).unwrap_or(0);
+    let mut themes = themes.selected(selected);
+    themes.set_on_select(|ui, name: &String| {
+        // A broken theme is reported under the list rather than previewed
+        let status = match theme::apply(ui, name) {
+            Ok(warnings) => warnings.join("\n"),
+            Err(e) => e.to_string(),
+        };
+        ui.call_on_name("theme_status", |view: &mut TextView| view.set_content(status));
+    });
+    themes.set_on_submit(save_theme);
+
+    ui.add_layer(Dialog::around(LinearLayout::vertical()
+            .child(TextView::new("Theme"))
+            .child(themes.with_name("theme").scrollable().max_height(12))
+            .child(TextView::new("").with_name("theme_status")))
+        .title("Settings")
+        .button("Save", |ui| {
+            let name = ui.call_on_name("theme", |view: &mut SelectView<String>| view.selection())
+                .flatten();
+            if let Some(name) = name {
+                save_theme(ui, &name);
+            }
+        })
+        .button("Cancel", |ui| {
+            let saved = app(ui).theme.clone();
+            if theme::apply(ui, &saved).is_err() {
+                theme::apply(ui, theme::DEFAULT_THEME).expect("The default theme loads");
+            }
+            ui.pop_layer();
+        }));
+}
+
+fn save_theme(ui: &mut Cursive, name: &String) {
+    if let Err(e) = theme::apply(ui, name) {
+        return show_error(ui, e);
+    }
+    match config::save_theme(name) {
+        Ok(_) => {
+            app(ui).theme = name.to_string();
+            ui.pop_layer();
+        }
+        Err(e) => show_error(ui, e),
+    }
+}
+
+/// The task overview of the Inbox, where quick-added tasks land
+fn inbox(ui: &mut Cursive) {
+    match store(ui).inbox().map(|project| project.id) {
+        Some(inbox_id) => task_overview(ui, &inbox_id),
+        None => ui.add_layer(Dialog::info("There is no Inbox yet, it appears after the first sync.")),
+    }
+}
+
+/// Rename a project and change its color and favorite flag, then `reload`
+fn edit_project(ui: &mut Cursive, project: &Project, reload: impl Fn(&mut Cursive) + 'static) {
+    let project_id = project.id;
+
+    let mut colors = SelectView::new().popup();
+    if !COLORS.iter().any(|(id, _)| *id == project.color) {
+        colors.add_item("Unchanged", project.color);
+    }
+    for (id, name) in COLORS.iter() {
+        colors.add_item(*name, *id);
+    }
+    let current = COLORS.iter().position(|(id, _)| *id == project.color).unwrap_or(0);
+    let colors = colors.selected(current);
+
+    ui.add_layer(Dialog::around(LinearLayout::vertical()
+            .child(TextView::new("Name"))
+            .child(EditView::new()
+                .content(project.name.to_string())
+                .with_name("project_name")
+                .min_width(20))
+            .child(TextView::new("\nColor"))
+            .child(colors.with_name("project_color"))
+            .child(LinearLayout::horizontal()
+                .child(Checkbox::new()
+                    .with_checked(project.favorite)
+                    .with_name("project_favorite"))
+                .child(TextView::new(" Favorite"))))
+        .title("Update project")
+        .button("Save", move |ui| {
+            let name = ui.call_on_name("project_name", |view: &mut EditView| view.get_content())
+                .unwrap();
+            let color = ui.call_on_name("project_color", |view: &mut SelectView<i64>| view.selection())
+                .flatten()
+                .unwrap();
+            let favorite = ui.call_on_name("project_favorite", |view: &mut Checkbox| view.is_checked())
+                .unwrap();
+            if name.trim().is_empty() {
+                ui.add_layer(Dialog::info("A project needs a name"));
+                return;
+            }
 
-    ui.run();
+            let json_data = json!({"name": name.as_str(), "color": *color, "favorite": favorite});
+            match mutate(ui, Mutation::UpdateProject { id: project_id, args: json_data }) {
+                Ok(_) => {
+                    ui.pop_layer();
+                    reload(ui);
+                }
+                Err(e) => show_error(ui, e),
+            }
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
 }
 
-fn task_overview(ui: &mut Cursive, id: &str) {
-    let section_vec = gather_sections(id).unwrap();
-    let task_vec = gather_tasks().unwrap();
+fn task_overview(ui: &mut Cursive, id: &i64) {
+    filtered_task_overview(ui, *id, None);
+}
+
+/// The task overview, limited to tasks carrying `label_filter` when it is set
+fn filtered_task_overview(ui: &mut Cursive, project_id: i64, label_filter: Option<i64>) {
+    draw_tasks(ui, project_id, label_filter);
+    sync_in_background(ui);
+}
+
+fn draw_tasks(ui: &mut Cursive, project_id: i64, label_filter: Option<i64>) {
+    let section_vec = gather_sections(ui, project_id);
+    let task_vec = gather_tasks(ui, project_id);
+    let label_vec = gather_labels(ui);
+    let task_vec: Vec<Task> = task_vec.into_iter()
+        .filter(|task| label_filter.is_none_or(|label_id| task.label_ids.contains(&label_id)))
+        .collect();
+    let query = app(ui).search.take();
 
     // Display the overview
     ui.pop_layer();
 
     let mut column = LinearLayout::vertical();
+    let mut shown_tasks = Vec::new();
+    let mut search = Search::new();
 
+    // Tasks outside every section come first, under their own heading
+    let unsectioned: Vec<&Task> = task_vec.iter()
+        .filter(|task| !section_vec.iter().any(|section| section.id == task.section_id))
+        .collect();
+    let mut groups = Vec::new();
+    if !unsectioned.is_empty() || section_vec.is_empty() {
+        groups.push(("(no section)".to_string(), unsectioned));
+    }
     for section in section_vec.iter() {
-        let mut select = SelectView::new();
-        for task in task_vec.iter() {
-            if task.section_id == section.id {
-                select.add_item(task.content.to_string(), task.id.to_string())
-            }
+        let tasks = task_vec.iter().filter(|task| task.section_id == section.id).collect();
+        groups.push((section.name.to_string(), tasks));
+    }
+
+    for (index, (heading, tasks)) in groups.into_iter().enumerate() {
+        let mut entries = Vec::new();
+        for task in tasks.into_iter() {
+            // Only the content is searched, the labels trail after it
+            let label = task_label(task, &label_vec);
+            let suffix = label[task.content.len()..].to_string();
+            entries.push(Entry { text: task.content.to_string(), suffix, id: task.id });
+            shown_tasks.push(task.clone());
         }
-        column.add_child(TextView::new(section.name.to_string()));
-        column.add_child(select);
+        let select = SelectView::new()
+            .on_submit(|ui, task_id: &i64| comment_panel(ui, CommentTarget::Task(*task_id)));
+        column.add_child(TextView::new(heading));
+        column.add_child(search.list(&format!("tasks_{}", index), select, entries, query.as_deref().unwrap_or("")));
     }
+    column.add_child(search_slot());
+    let search = Rc::new(search);
 
     // Display controls
-    let controls = "Complete [T]ask\n[C]reate Task/Section\n[D]elete Task/Section\n[U]pdate Task/Section\nGo [B]ack\n[S]ettings";
+    let controls = "Complete [T]ask\n[C]reate Task/Section\n[D]elete Task/Section\n[U]pdate Task/Section\nTask [L]abels\n[F]ilter by label\n[Enter] Task comments\nProject co[M]ments\n[K]anban board\n[/] Search\nGo [B]ack\nProject [S]ettings";
 
+    let project = store(ui).projects.get(&project_id).cloned();
+    let project_name = project_name(ui, project_id);
+    let title = match label_vec.iter().find(|label| Some(label.id) == label_filter) {
+        Some(label) => screen_title(ui, &format!("{} @{}", project_name, label.name)),
+        None => screen_title(ui, &project_name),
+    };
+
+    let reload = move |ui: &mut Cursive| filtered_task_overview(ui, project_id, label_filter);
+    let complete_tasks = shown_tasks.clone();
+    let delete_tasks = shown_tasks.clone();
+    let delete_sections = section_vec.clone();
+    let update_tasks = shown_tasks.clone();
+    let update_sections = section_vec.clone();
+    let update_labels = label_vec.clone();
+    let create_sections = section_vec.clone();
+    let create_labels = label_vec.clone();
+    let label_choices = label_vec.clone();
+    let open = search.clone();
+    let close = search.clone();
+    let next = search.clone();
+    let previous = search.clone();
     let callbacks = OnEventView::new(column)
-        .on_event('t', |ui| {})
-        .on_event('c', |ui| {})
-        .on_event('d', |ui| {})
-        .on_event('u', |ui| {})
+        // Complete a task
+        .on_event('t', move |ui| {
+            pick_task(ui, "Task to complete", &complete_tasks, move |ui, task| {
+                match mutate(ui, Mutation::CloseTask { id: task.id }) {
+                    Ok(_) => reload(ui),
+                    Err(e) => show_error(ui, e),
+                }
+            });
+        })
+        // Create a task or section
+        .on_event('c', move |ui| {
+            let sections = create_sections.clone();
+            let labels = create_labels.clone();
+            pick_kind(ui, "Create", move |ui, kind| match kind {
+                Kind::Task => task_form(ui, project_id, None, &sections, &labels, reload),
+                Kind::Section => section_form(ui, project_id, None, reload),
+            });
+        })
+        // Delete a task or section
+        .on_event('d', move |ui| {
+            let tasks = delete_tasks.clone();
+            let sections = delete_sections.clone();
+            pick_kind(ui, "Delete", move |ui, kind| match kind {
+                Kind::Task => pick_task(ui, "Task to delete", &tasks, move |ui, task| {
+                    let id = task.id;
+                    confirm(ui, &format!("Delete \"{}\"?", task.content), move |ui| {
+                        match mutate(ui, Mutation::DeleteTask { id }) {
+                            Ok(_) => reload(ui),
+                            Err(e) => show_error(ui, e),
+                        }
+                    });
+                }),
+                Kind::Section => pick_section(ui, "Section to delete", &sections, move |ui, section| {
+                    let id = section.id;
+                    confirm(ui, &format!("Delete \"{}\" and every task in it?", section.name), move |ui| {
+                        match mutate(ui, Mutation::DeleteSection { id }) {
+                            Ok(_) => reload(ui),
+                            Err(e) => show_error(ui, e),
+                        }
+                    });
+                }),
+            });
+        })
+        // Update a task or section
+        .on_event('u', move |ui| {
+            let tasks = update_tasks.clone();
+            let sections = update_sections.clone();
+            let labels = update_labels.clone();
+            pick_kind(ui, "Update", move |ui, kind| match kind {
+                Kind::Task => {
+                    let labels = labels.clone();
+                    pick_task(ui, "Task to update", &tasks, move |ui, task| {
+                        task_form(ui, project_id, Some(task), &[], &labels, reload);
+                    });
+                }
+                Kind::Section => pick_section(ui, "Section to update", &sections, move |ui, section| {
+                    section_form(ui, project_id, Some(section), reload);
+                }),
+            });
+        })
+        // Edit a task's labels
+        .on_event('l', move |ui| {
+            let labels = label_choices.clone();
+            pick_task(ui, "Task to label", &shown_tasks, move |ui, task| {
+                edit_task_labels(ui, task, &labels, reload);
+            });
+        })
+        // Filter by label
+        .on_event('f', move |ui| {
+            let mut sub_select = SelectView::new();
+            sub_select.add_item("All tasks", None);
+            for label in label_vec.iter() {
+                sub_select.add_item(format!("@{}", label.name), Some(label.id));
+            }
+            sub_select.set_on_submit(move |ui, label_id: &Option<i64>| {
+                ui.pop_layer();
+                filtered_task_overview(ui, project_id, *label_id);
+            });
+
+            ui.add_layer(Dialog::around(sub_select.scrollable())
+                .title("Filter by label")
+                .button("Back", |ui| { ui.pop_layer(); }));
+        })
+        .on_event('m', move |ui| comment_panel(ui, CommentTarget::Project(project_id)))
+        .on_event('k', move |ui| board_overview(ui, project_id))
+        .on_event('/', move |ui| open_search(ui, &open, ""))
+        .on_event('n', move |ui| step_result(ui, &next, true))
+        .on_event('N', move |ui| step_result(ui, &previous, false))
+        .on_event(Key::Esc, move |ui| close_search(ui, &close))
         .on_event('b', project_overview)
-        .on_event('s', |ui| {});
+        // Edit the project itself
+        .on_event('s', move |ui| {
+            if let Some(project) = &project {
+                edit_project(ui, project, reload);
+            }
+        });
 
+    let status = status_view(ui);
     ui.add_layer(Dialog::around(LinearLayout::horizontal()
             .child(callbacks)
-            .child(TextView::new(controls)))
-        .title("Tasks")
+            .child(LinearLayout::vertical()
+                .child(TextView::new(controls))
+                .child(status)))
+        .title(title)
         .h_align(HAlign::Center));
+    if let Some(query) = query {
+        open_search(ui, &search, &query);
+    }
+    set_screen(ui, move |ui| draw_tasks(ui, project_id, label_filter));
+}
+
+/// Which agenda to show
+#[derive(Clone, Copy)]
+enum Agenda {
+    Today,
+    /// The next so many days, today included
+    Upcoming(i64),
 }
 
-fn gather_projects() -> Result<Vec<Project>, Box<dyn std::error::Error>> {
-    let conf: config::Config = confy::load("coto").expect("Could not load config");
-    let key: String = conf.todoist_key;
+/// Tasks due soon across every project
+fn agenda_overview(ui: &mut Cursive, view: Agenda) {
+    draw_agenda(ui, view);
+    sync_in_background(ui);
+}
 
-    let project_call = Runtime::new().expect("Could not query projects")
-        .block_on(query::get_all_projects(&key))
-        .unwrap();
+fn draw_agenda(ui: &mut Cursive, view: Agenda) {
+    let today = agenda::today();
+    let task_vec = gather_all_tasks(ui);
+    let label_vec = gather_labels(ui);
 
-    let project_count = project_call.matches("}").count();
-    let project_json: Value = serde_json::from_str(&project_call).unwrap();
-    let mut project_vec: Vec<Project> = Vec::new();
-    for p in 0..project_count {
-        project_vec.push(Project {
-            count: p as i32,
-            id: project_json[p]["id"].to_string(),
-            name: project_json[p]["name"].to_string(),
-            shared: project_json[p]["shared"].to_string(),
+    let (title, groups) = match view {
+        Agenda::Today => {
+            let (overdue, due): (Vec<Task>, Vec<Task>) = agenda::due_today(&task_vec, today)
+                .into_iter()
+                .partition(|task| agenda::due_date(task) < Some(today));
+            let groups = vec![("Overdue".to_string(), overdue), ("Today".to_string(), due)];
+            ("Today".to_string(), groups)
+        }
+        Agenda::Upcoming(days) => {
+            let groups = agenda::upcoming(&task_vec, today, days)
+                .into_iter()
+                .map(|(date, tasks)| (date.format("%A %-d %B").to_string(), tasks))
+                .collect();
+            (format!("Next {} days", days), groups)
+        }
+    };
+
+    // Display the agenda
+    ui.pop_layer();
+
+    let mut column = LinearLayout::vertical();
+    let mut shown_tasks = Vec::new();
+    for (heading, tasks) in groups.into_iter().filter(|(_, tasks)| !tasks.is_empty()) {
+        let mut select = SelectView::new();
+        for task in tasks.into_iter() {
+            let project = project_name(ui, task.project_id);
+            select.add_item(format!("{}  #{}", task_label(&task, &label_vec), project), task.id);
+            shown_tasks.push(task);
+        }
+        select.set_on_submit(|ui, task_id: &i64| comment_panel(ui, CommentTarget::Task(*task_id)));
+        column.add_child(TextView::new(heading));
+        column.add_child(select);
+    }
+    if shown_tasks.is_empty() {
+        column.add_child(TextView::new("Nothing due."));
+    }
+
+    // Display controls
+    let controls = match view {
+        Agenda::Today => "Complete [T]ask\n[Enter] Task comments\nGo [B]ack",
+        Agenda::Upcoming(_) => "Complete [T]ask\n[Enter] Task comments\n[W]eek or two\nGo [B]ack",
+    };
+
+    let title = screen_title(ui, &title);
+    let callbacks = OnEventView::new(column)
+        // Complete a task
+        .on_event('t', move |ui| {
+            pick_task(ui, "Task to complete", &shown_tasks, move |ui, task| {
+                match mutate(ui, Mutation::CloseTask { id: task.id }) {
+                    Ok(_) => agenda_overview(ui, view),
+                    Err(e) => show_error(ui, e),
+                }
+            });
+        })
+        // Switch between one week and two
+        .on_event('w', move |ui| {
+            if let Agenda::Upcoming(days) = view {
+                let days = if days == agenda::UPCOMING_DAYS { agenda::UPCOMING_DAYS_LONG } else { agenda::UPCOMING_DAYS };
+                draw_agenda(ui, Agenda::Upcoming(days));
+            }
+        })
+        .on_event('b', project_overview);
+
+    let status = status_view(ui);
+    ui.add_layer(Dialog::around(LinearLayout::horizontal()
+            .child(callbacks.scrollable())
+            .child(DummyView.fixed_width(2))
+            .child(LinearLayout::vertical()
+                .child(TextView::new(controls))
+                .child(status)))
+        .title(title)
+        .h_align(HAlign::Center));
+    set_screen(ui, move |ui| draw_agenda(ui, view));
+}
+
+/// Ask for a Todoist filter query to list the tasks of
+fn filter_prompt(ui: &mut Cursive) {
+    ui.add_layer(Dialog::new()
+        .content(EditView::new()
+            .on_submit(|ui, query| {
+                if query.trim().is_empty() {
+                    return;
+                }
+                ui.pop_layer();
+                run_view(ui, SavedView::query(query));
+            })
+            .min_width(30))
+        .title("Filter, e.g. (today | overdue) & p1")
+        .button("Back", |ui| { ui.pop_layer(); }));
+}
+
+/// Ask Todoist which tasks the view's filter picks, then show them. Only the
+/// ids are kept, the tasks themselves are read from the store so that changes
+/// made here show up straight away.
+fn run_view(ui: &mut Cursive, view: SavedView) {
+    let filter = view.query_filter().map(str::to_string);
+    let lang = app(ui).filter_lang.clone().filter(|_| filter.is_some());
+    let request = move |client: TodoistClient| async move {
+        client.get_all_tasks(filter.as_deref(), lang.as_deref()).await
+    };
+    fetch(ui, request, move |ui, result| {
+        // Without Todoist the filter is run against the local cache instead
+        let result = match result {
+            Err(CotoError::Network(_)) => view::run_locally(&view, store(ui), agenda::today()),
+            result => result,
+        };
+        match result {
+            Ok(tasks) => {
+                let task_ids = tasks.iter().map(|task| task.id).collect();
+                view_overview(ui, Rc::new(view), Rc::new(task_ids));
+            }
+            Err(e) => show_retry_error(ui, e, move |ui| run_view(ui, view.clone())),
+        }
+    });
+}
+
+fn view_overview(ui: &mut Cursive, view: Rc<SavedView>, task_ids: Rc<Vec<i64>>) {
+    draw_view(ui, view, task_ids);
+    sync_in_background(ui);
+}
+
+fn draw_view(ui: &mut Cursive, view: Rc<SavedView>, task_ids: Rc<Vec<i64>>) {
+    let task_vec: Vec<Task> = task_ids.iter()
+        .filter_map(|id| store(ui).tasks.get(id).cloned())
+        .collect();
+    let project_vec = gather_projects(ui);
+    let label_vec = gather_labels(ui);
+    let groups = view::arrange(&view, task_vec.clone(), &project_vec, &label_vec, agenda::today());
+
+    // Display the results
+    ui.pop_layer();
+
+    let mut layout = match view.layout {
+        Layout::List => LinearLayout::vertical(),
+        Layout::Board => LinearLayout::horizontal(),
+    };
+    for (heading, tasks) in groups.into_iter() {
+        let mut select = SelectView::new();
+        for task in tasks.iter() {
+            let project = project_name(ui, task.project_id);
+            select.add_item(format!("{}  #{}", task_label(task, &label_vec), project), task.id);
+        }
+        select.set_on_submit(|ui, task_id: &i64| comment_panel(ui, CommentTarget::Task(*task_id)));
+        match view.layout {
+            Layout::List => {
+                if !heading.is_empty() {
+                    layout.add_child(TextView::new(heading));
+                }
+                layout.add_child(select);
+            }
+            Layout::Board => layout.add_child(Panel::new(select.scrollable()).title(heading).fixed_width(28)),
+        }
+    }
+    if task_vec.is_empty() {
+        layout.add_child(TextView::new("No tasks match."));
+    }
+
+    // Display controls
+    let controls = "Complete [T]ask\n[D]elete Task\n[U]pdate Task\nTask [L]abels\n[Enter] Task comments\n[R]un again\nGo [B]ack";
+
+    let title = screen_title(ui, &view.name);
+    let reload_view = view.clone();
+    let reload_ids = task_ids.clone();
+    let reload = move |ui: &mut Cursive| view_overview(ui, reload_view.clone(), reload_ids.clone());
+    let complete_tasks = task_vec.clone();
+    let delete_tasks = task_vec.clone();
+    let delete_reload = reload.clone();
+    let update_tasks = task_vec.clone();
+    let update_labels = label_vec.clone();
+    let update_reload = reload.clone();
+    let label_reload = reload.clone();
+    let rerun_view = view.clone();
+    let callbacks = OnEventView::new(layout)
+        // Complete a task
+        .on_event('t', move |ui| {
+            let reload = reload.clone();
+            pick_task(ui, "Task to complete", &complete_tasks, move |ui, task| {
+                match mutate(ui, Mutation::CloseTask { id: task.id }) {
+                    Ok(_) => reload(ui),
+                    Err(e) => show_error(ui, e),
+                }
+            });
+        })
+        // Delete a task
+        .on_event('d', move |ui| {
+            let reload = delete_reload.clone();
+            pick_task(ui, "Task to delete", &delete_tasks, move |ui, task| {
+                let id = task.id;
+                let reload = reload.clone();
+                confirm(ui, &format!("Delete \"{}\"?", task.content), move |ui| {
+                    match mutate(ui, Mutation::DeleteTask { id }) {
+                        Ok(_) => reload(ui),
+                        Err(e) => show_error(ui, e),
+                    }
+                });
+            });
+        })
+        // Update a task
+        .on_event('u', move |ui| {
+            let labels = update_labels.clone();
+            let reload = update_reload.clone();
+            pick_task(ui, "Task to update", &update_tasks, move |ui, task| {
+                task_form(ui, task.project_id, Some(task), &[], &labels, reload.clone());
+            });
+        })
+        // Edit a task's labels
+        .on_event('l', move |ui| {
+            let labels = label_vec.clone();
+            let reload = label_reload.clone();
+            pick_task(ui, "Task to label", &task_vec, move |ui, task| {
+                edit_task_labels(ui, task, &labels, reload.clone());
+            });
+        })
+        .on_event('r', move |ui| run_view(ui, SavedView::clone(&rerun_view)))
+        .on_event('b', project_overview);
+
+    let status = status_view(ui);
+    ui.add_layer(Dialog::around(LinearLayout::horizontal()
+            .child(callbacks.scrollable().scroll_x(view.layout == Layout::Board))
+            .child(DummyView.fixed_width(2))
+            .child(LinearLayout::vertical()
+                .child(TextView::new(controls))
+                .child(status)))
+        .title(title)
+        .h_align(HAlign::Center));
+    set_screen(ui, move |ui| draw_view(ui, view.clone(), task_ids.clone()));
+}
+
+/// One column of the board: a section, or the tasks outside every section
+struct Column {
+    section_id: Option<i64>,
+    name: String,
+    tasks: Vec<Task>,
+}
+
+/// The tasks of a project laid out as a board, one column per section
+fn board_overview(ui: &mut Cursive, project_id: i64) {
+    draw_board(ui, project_id, None);
+    sync_in_background(ui);
+}
+
+/// Draw the board, keeping the cursor on `selected` if it is still there
+fn draw_board(ui: &mut Cursive, project_id: i64, selected: Option<i64>) {
+    let section_vec = gather_sections(ui, project_id);
+    let task_vec = gather_tasks(ui, project_id);
+    let label_vec = gather_labels(ui);
+
+    // Always a column for tasks outside every section, so they can be moved back out
+    let mut columns = vec![Column {
+        section_id: None,
+        name: "(no section)".to_string(),
+        tasks: task_vec.iter()
+            .filter(|task| !section_vec.iter().any(|section| section.id == task.section_id))
+            .cloned()
+            .collect(),
+    }];
+    for section in section_vec.iter() {
+        columns.push(Column {
+            section_id: Some(section.id),
+            name: section.name.to_string(),
+            tasks: task_vec.iter().filter(|task| task.section_id == section.id).cloned().collect(),
         });
     }
 
-    println!("{:?}", project_vec);
-    Ok(project_vec)
+    // Display the board
+    ui.pop_layer();
+
+    let mut board = LinearLayout::horizontal();
+    let mut focus = None;
+    for (index, column) in columns.iter().enumerate() {
+        let mut select = SelectView::new();
+        for task in column.tasks.iter() {
+            select.add_item(task_label(task, &label_vec), task.id);
+        }
+        if let Some(row) = column.tasks.iter().position(|task| Some(task.id) == selected) {
+            select.set_selection(row);
+            focus = Some(index);
+        }
+        select.set_on_submit(|ui, task_id: &i64| comment_panel(ui, CommentTarget::Task(*task_id)));
+        board.add_child(Panel::new(select.with_name(column_name(index)).scrollable())
+            .title(column.name.as_str())
+            .fixed_width(28));
+    }
+    if let Some(index) = focus {
+        let _ = board.set_focus_index(index);
+    }
+
+    // Display controls
+    let controls = "[H] [L] Move task left/right\n[K] [J] Move task up/down\n[Enter] Task comments\nGo [B]ack to list";
+
+    let project_name = project_name(ui, project_id);
+    let title = screen_title(ui, &format!("{} board", project_name));
+    let columns = Rc::new(columns);
+    let left = columns.clone();
+    let right = columns.clone();
+    let up = columns.clone();
+    let callbacks = OnEventView::new(board.with_name("board").scrollable().scroll_x(true))
+        .on_event('h', move |ui| move_to_column(ui, project_id, &left, -1))
+        .on_event('l', move |ui| move_to_column(ui, project_id, &right, 1))
+        .on_event('k', move |ui| move_in_column(ui, project_id, &up, -1))
+        .on_event('j', move |ui| move_in_column(ui, project_id, &columns, 1))
+        .on_event('b', move |ui| filtered_task_overview(ui, project_id, None));
+
+    let status = status_view(ui);
+    ui.add_layer(Dialog::around(LinearLayout::vertical()
+            .child(callbacks)
+            .child(TextView::new(controls))
+            .child(status))
+        .title(title)
+        .h_align(HAlign::Center));
+    set_screen(ui, move |ui| {
+        let selected = board_cursor(ui).map(|(_, task_id)| task_id);
+        draw_board(ui, project_id, selected);
+    });
+}
+
+fn column_name(index: usize) -> String {
+    format!("board_column_{}", index)
+}
+
+/// The focused column and the task selected in it
+fn board_cursor(ui: &mut Cursive) -> Option<(usize, i64)> {
+    let index = ui.call_on_name("board", |board: &mut LinearLayout| board.get_focus_index())?;
+    let task_id = ui.call_on_name(&column_name(index), |column: &mut SelectView<i64>| column.selection())??;
+    Some((index, *task_id))
+}
+
+/// Move the selected task to the bottom of the column `step` away
+fn move_to_column(ui: &mut Cursive, project_id: i64, columns: &[Column], step: isize) {
+    let (index, task_id) = match board_cursor(ui) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let target = index as isize + step;
+    if target < 0 || target as usize >= columns.len() {
+        return;
+    }
+    let target = &columns[target as usize];
+
+    let args = match target.section_id {
+        Some(section_id) => json!({"section_id": section_id}),
+        None => json!({"project_id": project_id}),
+    };
+    let order = target.tasks.iter().map(|task| task.order).max().unwrap_or(0) + 1;
+    let moved = mutate(ui, Mutation::MoveTask { id: task_id, args, base: None })
+        .and_then(|_| mutate(ui, Mutation::ReorderTasks { orders: vec![(task_id, order)] }));
+    match moved {
+        Ok(_) => {
+            draw_board(ui, project_id, Some(task_id));
+            sync_in_background(ui);
+        }
+        Err(e) => show_error(ui, e),
+    }
+}
+
+/// Swap the selected task with its neighbour `step` rows away, numbering
+/// the whole column afresh so tasks that shared an order are kept apart
+fn move_in_column(ui: &mut Cursive, project_id: i64, columns: &[Column], step: isize) {
+    let (index, task_id) = match board_cursor(ui) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let mut task_ids: Vec<i64> = columns[index].tasks.iter().map(|task| task.id).collect();
+    let row = match task_ids.iter().position(|id| *id == task_id) {
+        Some(row) => row,
+        None => return,
+    };
+    let target = row as isize + step;
+    if target < 0 || target as usize >= task_ids.len() {
+        return;
+    }
+    task_ids.swap(row, target as usize);
+
+    let orders = task_ids.into_iter().zip(1..).collect();
+    match mutate(ui, Mutation::ReorderTasks { orders }) {
+        Ok(_) => {
+            draw_board(ui, project_id, Some(task_id));
+            sync_in_background(ui);
+        }
+        Err(e) => show_error(ui, e),
+    }
+}
+
+/// Whether an action applies to a task or a section
+#[derive(Clone, Copy)]
+enum Kind {
+    Task,
+    Section,
+}
+
+fn pick_kind(ui: &mut Cursive, title: &str, on_pick: impl Fn(&mut Cursive, Kind) + 'static) {
+    let mut sub_select = SelectView::new();
+    sub_select.add_item("Task", Kind::Task);
+    sub_select.add_item("Section", Kind::Section);
+    sub_select.set_on_submit(move |ui, kind: &Kind| {
+        ui.pop_layer();
+        on_pick(ui, *kind);
+    });
+
+    ui.add_layer(Dialog::around(sub_select)
+        .title(title)
+        .button("Back", |ui| { ui.pop_layer(); }));
+}
+
+fn pick_task(ui: &mut Cursive, title: &str, tasks: &[Task], on_pick: impl Fn(&mut Cursive, &Task) + 'static) {
+    let mut sub_select = SelectView::new();
+    for task in tasks.iter() {
+        sub_select.add_item(task.content.to_string(), task.clone());
+    }
+    sub_select.set_on_submit(move |ui, task: &Task| {
+        ui.pop_layer();
+        on_pick(ui, task);
+    });
+
+    ui.add_layer(Dialog::around(sub_select.scrollable())
+        .title(title)
+        .button("Back", |ui| { ui.pop_layer(); }));
 }
 
-fn gather_sections(id: &str) -> Result<Vec<Section>, Box<dyn std::error::Error>> {
-    let conf: config::Config = confy::load("coto").expect("Could not load config");
-    let key: String = conf.todoist_key;
+fn pick_section(ui: &mut Cursive, title: &str, sections: &[Section], on_pick: impl Fn(&mut Cursive, &Section) + 'static) {
+    let mut sub_select = SelectView::new();
+    for section in sections.iter() {
+        sub_select.add_item(section.name.to_string(), section.clone());
+    }
+    sub_select.set_on_submit(move |ui, section: &Section| {
+        ui.pop_layer();
+        on_pick(ui, section);
+    });
+
+    ui.add_layer(Dialog::around(sub_select.scrollable())
+        .title(title)
+        .button("Back", |ui| { ui.pop_layer(); }));
+}
+
+/// Ask before doing something that cannot be undone
+fn confirm(ui: &mut Cursive, question: &str, on_yes: impl Fn(&mut Cursive) + 'static) {
+    ui.add_layer(Dialog::text(format!("{}\nOnce it's gone - it's gone.", question))
+        .title("Are you sure?")
+        .button("Delete", move |ui| {
+            ui.pop_layer();
+            on_yes(ui);
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+/// Create a task in `sections` of a project, or change `task` when given.
+/// Todoist only sets the section when a task is created.
+fn task_form(ui: &mut Cursive, project_id: i64, task: Option<&Task>, sections: &[Section], labels: &[Label],
+    reload: impl Fn(&mut Cursive) + 'static) {
+    let task = task.cloned();
+    let labels = labels.to_vec();
+
+    let mut form = LinearLayout::vertical()
+        .child(TextView::new("Content"))
+        .child(EditView::new()
+            .content(task.as_ref().map_or("", |task| task.content.as_str()))
+            .with_name("task_content")
+            .min_width(30));
+
+    if task.is_none() {
+        let mut section_select = SelectView::new().popup();
+        section_select.add_item("(no section)", 0);
+        for section in sections.iter() {
+            section_select.add_item(section.name.to_string(), section.id);
+        }
+        form.add_child(TextView::new("\nSection"));
+        form.add_child(section_select.with_name("task_section"));
+    }
+
+    let mut priority_select = SelectView::new().popup();
+    for (priority, name) in [(1, "1 (normal)"), (2, "2"), (3, "3"), (4, "4 (urgent)")].iter() {
+        priority_select.add_item(*name, *priority);
+    }
+    let priority = task.as_ref().map_or(1, |task| task.priority);
+    form.add_child(TextView::new("\nPriority"));
+    form.add_child(priority_select.selected((priority - 1).max(0) as usize).with_name("task_priority"));
 
-    let section_call = Runtime::new().expect("Could not query sections")
-        .block_on(query::get_project_sections(&key, &id.to_string()))
-        .unwrap();
-    let section_count = section_call.matches("}").count();
-    let section_json: Value = serde_json::from_str(&section_call).unwrap();
+    form.add_child(TextView::new("\nDue, e.g. \"tomorrow 5pm\""));
+    form.add_child(EditView::new()
+        .content(task.as_ref().and_then(|task| task.due.as_ref()).map_or("", |due| due.string.as_str()))
+        .with_name("task_due"));
 
-    let mut section_vec: Vec<Section> = Vec::new();
-    let mut section_counter: i32 = 1;
-    for s in 0..section_count {
-        section_vec.push(Section {
-            id: section_json[s]["id"].to_string(),
-            project_id: section_json[s]["project_id"].to_string(),
-            order: section_counter,
-            real_order: section_json[s]["order"].to_string(),
-            name: section_json[s]["name"].to_string(),
+    let selected = task.as_ref().map_or(Vec::new(), |task| task.label_ids.clone());
+    form.add_child(TextView::new("\nLabels"));
+    form.add_child(label_checklist(&labels, &selected));
+
+    let title = if task.is_some() { "Update task" } else { "New task" };
+    ui.add_layer(Dialog::around(form.scrollable())
+        .title(title)
+        .button("Save", move |ui| {
+            let content = ui.call_on_name("task_content", |view: &mut EditView| view.get_content()).unwrap();
+            let section_id = ui.call_on_name("task_section", |view: &mut SelectView<i64>| view.selection())
+                .flatten();
+            let priority = ui.call_on_name("task_priority", |view: &mut SelectView<i64>| view.selection())
+                .flatten()
+                .map_or(1, |priority| *priority);
+            let due = ui.call_on_name("task_due", |view: &mut EditView| view.get_content()).unwrap();
+            let label_ids = checked_labels(ui, &labels);
+            if content.trim().is_empty() {
+                return;
+            }
+
+            let mutation = match &task {
+                // Only send what changed, so untouched fields never conflict
+                Some(task) => {
+                    let mut args = serde_json::Map::new();
+                    if *content != task.content {
+                        args.insert("content".to_string(), json!(content.as_str()));
+                    }
+                    if priority != task.priority {
+                        args.insert("priority".to_string(), json!(priority));
+                    }
+                    if Some(due.as_str()) != task.due.as_ref().map(|due| due.string.as_str()) {
+                        args.insert("due_string".to_string(), json!(due.as_str()));
+                    }
+                    if label_ids != task.label_ids {
+                        args.insert("label_ids".to_string(), json!(label_ids));
+                    }
+                    if args.is_empty() {
+                        ui.pop_layer();
+                        return;
+                    }
+                    Mutation::UpdateTask { id: task.id, args: args.into(), base: None }
+                }
+                None => {
+                    let mut args = json!({
+                        "content": content.as_str(),
+                        "project_id": project_id,
+                        "priority": priority,
+                        "label_ids": label_ids,
+                    });
+                    if let Some(section_id) = section_id.filter(|id| **id != 0) {
+                        args["section_id"] = json!(*section_id);
+                    }
+                    if !due.trim().is_empty() {
+                        args["due_string"] = json!(due.as_str());
+                    }
+                    Mutation::NewTask { temp_id: app(ui).outbox.temp_id(), args }
+                }
+            };
+
+            match mutate(ui, mutation) {
+                Ok(_) => {
+                    ui.pop_layer();
+                    reload(ui);
+                }
+                Err(e) => show_error(ui, e),
+            }
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+/// Create a section in a project, or rename `section` when given
+fn section_form(ui: &mut Cursive, project_id: i64, section: Option<&Section>, reload: impl Fn(&mut Cursive) + 'static) {
+    let section_id = section.map(|section| section.id);
+
+    ui.add_layer(Dialog::around(EditView::new()
+            .content(section.map_or("", |section| section.name.as_str()))
+            .with_name("section_name")
+            .min_width(20))
+        .title(if section.is_some() { "Rename section" } else { "New section name" })
+        .button("Save", move |ui| {
+            let name = ui.call_on_name("section_name", |view: &mut EditView| view.get_content()).unwrap();
+            if name.trim().is_empty() {
+                return;
+            }
+
+            let mutation = match section_id {
+                Some(id) => Mutation::UpdateSection { id, args: json!({"name": name.as_str()}) },
+                None => Mutation::NewSection {
+                    temp_id: app(ui).outbox.temp_id(),
+                    args: json!({"name": name.as_str(), "project_id": project_id}),
+                },
+            };
+            match mutate(ui, mutation) {
+                Ok(_) => {
+                    ui.pop_layer();
+                    reload(ui);
+                }
+                Err(e) => show_error(ui, e),
+            }
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+/// A task's content followed by the names of its labels
+fn task_label(task: &Task, labels: &[Label]) -> String {
+    let mut text = task.content.to_string();
+    for label in labels.iter().filter(|label| task.label_ids.contains(&label.id)) {
+        text.push_str(&format!(" @{}", label.name));
+    }
+    text
+}
+
+/// One checkbox per label, ticked for those in `selected`
+fn label_checklist(labels: &[Label], selected: &[i64]) -> LinearLayout {
+    let mut list = LinearLayout::vertical();
+    for label in labels.iter() {
+        list.add_child(LinearLayout::horizontal()
+            .child(Checkbox::new()
+                .with_checked(selected.contains(&label.id))
+                .with_name(format!("label_{}", label.id)))
+            .child(TextView::new(format!(" @{}", label.name))));
+    }
+    list
+}
+
+/// The ids of every label ticked in a `label_checklist`
+fn checked_labels(ui: &mut Cursive, labels: &[Label]) -> Vec<i64> {
+    labels.iter()
+        .filter(|label| ui.call_on_name(&format!("label_{}", label.id), |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false))
+        .map(|label| label.id)
+        .collect()
+}
+
+fn edit_task_labels(ui: &mut Cursive, task: &Task, labels: &[Label], reload: impl Fn(&mut Cursive) + 'static) {
+    let task_id = task.id;
+    let labels = labels.to_vec();
+
+    ui.add_layer(Dialog::around(label_checklist(&labels, &task.label_ids).scrollable())
+        .title(format!("Labels for {}", task.content))
+        .button("Save", move |ui| {
+            let json_data = json!({"label_ids": checked_labels(ui, &labels)});
+            match mutate(ui, Mutation::UpdateTask { id: task_id, args: json_data, base: None }) {
+                Ok(_) => {
+                    ui.pop_layer();
+                    reload(ui);
+                }
+                Err(e) => show_error(ui, e),
+            }
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+fn label_manager(ui: &mut Cursive) {
+    draw_labels(ui);
+    sync_in_background(ui);
+}
+
+fn draw_labels(ui: &mut Cursive) {
+    let label_vec = gather_labels(ui);
+
+    let mut select = SelectView::new();
+    for label in label_vec.into_iter() {
+        select.add_item(format!("@{}", label.name), label);
+    }
+    select.set_on_submit(edit_label);
+
+    let status = status_view(ui);
+    ui.add_layer(Dialog::around(LinearLayout::vertical()
+            .child(select.scrollable())
+            .child(status))
+        .title("Labels")
+        .button("New", |ui| {
+            fn ok(ui: &mut Cursive, name: &str) {
+                ui.pop_layer();
+                create_label(ui, name.to_string());
+            }
+
+            ui.add_layer(Dialog::new()
+                .content(EditView::new().on_submit(ok).min_width(20))
+                .title("New label name"));
+        })
+        .button("Back", |ui| { ui.pop_layer(); }));
+    set_screen(ui, |ui| {
+        ui.pop_layer();
+        draw_labels(ui);
+    });
+}
+
+fn edit_label(ui: &mut Cursive, label: &Label) {
+    let label_id = label.id;
+
+    ui.add_layer(Dialog::around(EditView::new()
+            .content(label.name.to_string())
+            .with_name("label_name")
+            .min_width(20))
+        .title("Edit label")
+        .button("Save", move |ui| {
+            let name = ui.call_on_name("label_name", |view: &mut EditView| view.get_content())
+                .unwrap();
+            ui.pop_layer();
+            rename_label(ui, label_id, name.to_string());
+        })
+        .button("Delete", move |ui| {
+            ui.pop_layer();
+            remove_label(ui, label_id);
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+fn create_label(ui: &mut Cursive, name: String) {
+    let json_name = json!({"name": name}).to_string();
+    fetch(ui, |client| async move { client.new_label(json_name).await }, move |ui, result| {
+        match result {
+            Ok(_) => sync_in_background(ui),
+            Err(e) => show_retry_error(ui, e, move |ui| create_label(ui, name.clone())),
+        }
+    });
+}
+
+fn rename_label(ui: &mut Cursive, label_id: i64, name: String) {
+    let json_name = json!({"name": name}).to_string();
+    fetch(ui, |client| async move { client.update_label(label_id, json_name).await }, move |ui, result| {
+        match result {
+            Ok(_) => sync_in_background(ui),
+            Err(e) => show_retry_error(ui, e, move |ui| rename_label(ui, label_id, name.clone())),
+        }
+    });
+}
+
+fn remove_label(ui: &mut Cursive, label_id: i64) {
+    fetch(ui, |client| async move { client.delete_label(label_id).await }, move |ui, result| {
+        match result {
+            Ok(_) => sync_in_background(ui),
+            Err(e) => show_retry_error(ui, e, move |ui| remove_label(ui, label_id)),
+        }
+    });
+}
+
+fn comment_panel(ui: &mut Cursive, target: CommentTarget) {
+    let title = match target {
+        CommentTarget::Task(_) => "Task comments",
+        CommentTarget::Project(_) => "Project comments",
+    };
+
+    ui.add_layer(Dialog::around(LinearLayout::vertical()
+            .child(LinearLayout::vertical()
+                .child(TextView::new("Loading comments..."))
+                .with_name("comment_thread"))
+            .child(TextView::new("\nNew comment:"))
+            .child(TextArea::new().with_name("new_comment").min_height(3)))
+        .title(title)
+        .button("Post", move |ui| post_comment(ui, target))
+        .button("Back", |ui| { ui.pop_layer(); }));
+    load_comments(ui, target);
+}
+
+/// Fetch a thread of comments into the comment panel, if it is still open
+fn load_comments(ui: &mut Cursive, target: CommentTarget) {
+    let request = |client: TodoistClient| async move {
+        match target {
+            CommentTarget::Task(id) => client.get_task_comments(id).await,
+            CommentTarget::Project(id) => client.get_project_comments(id).await,
+        }
+    };
+    fetch(ui, request, move |ui, result| {
+        let comment_vec = match result {
+            Ok(comments) => comments,
+            Err(e) => return show_retry_error(ui, e, move |ui| load_comments(ui, target)),
+        };
+
+        ui.call_on_name("comment_thread", |thread: &mut LinearLayout| {
+            while !thread.is_empty() {
+                thread.remove_child(0);
+            }
+            if comment_vec.is_empty() {
+                thread.add_child(TextView::new("No comments yet."));
+            } else {
+                let mut select = SelectView::new();
+                for comment in comment_vec.into_iter() {
+                    let posted = comment.posted.split('T').next().unwrap_or("").to_string();
+                    select.add_item(format!("{}  {}", posted, comment.content), comment);
+                }
+                select.set_on_submit(move |ui, comment: &Comment| edit_comment(ui, target, comment));
+                thread.add_child(select.scrollable().max_height(15));
+            }
         });
-        section_counter += 1;
-    }
-
-    Ok(section_vec)
-}
-
-fn gather_tasks() -> Result<Vec<Task>, Box<dyn std::error::Error>> {
-    let conf: config::Config = confy::load("coto").expect("Could not load config");
-    let key: String = conf.todoist_key;
-
-    let task_call = Runtime::new().expect("Could not query tasks")
-        .block_on(query::get_all_tasks(&key))
-        .unwrap();
-    let task_count = task_call.matches("}").count();
-    let task_json: Value = serde_json::from_str(&task_call).unwrap();
-
-    let mut task_vec: Vec<Task> = Vec::new();
-    let mut task_counter: i32 = 1;
-    for t in 0..task_count {
-        task_vec.push(Task {
-            id: task_json[t]["id"].to_string(),
-            section_id: task_json[t]["section_id"].to_string(),
-            order: task_counter,
-            content: task_json[t]["content"].to_string(),
-            priority: task_json[t]["priority"].to_string(),
-            created: task_json[t]["created"].to_string(),
-            due: task_json[t]["due"]["date"].to_string(),
-            real_order: task_json[t]["order"].to_string(),
+    });
+}
+
+fn post_comment(ui: &mut Cursive, target: CommentTarget) {
+    let content = ui.call_on_name("new_comment", |view: &mut TextArea| view.get_content().to_string())
+        .unwrap_or_default();
+    if content.trim().is_empty() {
+        return;
+    }
+
+    let json_data = match target {
+        CommentTarget::Task(id) => json!({"task_id": id, "content": content}),
+        CommentTarget::Project(id) => json!({"project_id": id, "content": content}),
+    }.to_string();
+    fetch(ui, |client| async move { client.new_comment(json_data).await }, move |ui, result| {
+        match result {
+            Ok(_) => {
+                ui.call_on_name("new_comment", |view: &mut TextArea| view.set_content(""));
+                load_comments(ui, target);
+            }
+            Err(e) => show_retry_error(ui, e, move |ui| post_comment(ui, target)),
+        }
+    });
+}
+
+fn edit_comment(ui: &mut Cursive, target: CommentTarget, comment: &Comment) {
+    let comment_id = comment.id;
+
+    ui.add_layer(Dialog::around(TextArea::new()
+            .content(comment.content.to_string())
+            .with_name("edit_comment")
+            .min_width(40)
+            .min_height(3))
+        .title("Edit comment")
+        .button("Save", move |ui| {
+            let content = ui.call_on_name("edit_comment", |view: &mut TextArea| view.get_content().to_string())
+                .unwrap_or_default();
+            ui.pop_layer();
+            save_comment(ui, target, comment_id, content);
+        })
+        .button("Delete", move |ui| {
+            ui.pop_layer();
+            remove_comment(ui, target, comment_id);
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+fn save_comment(ui: &mut Cursive, target: CommentTarget, comment_id: i64, content: String) {
+    let json_data = json!({"content": content}).to_string();
+    fetch(ui, |client| async move { client.update_comment(comment_id, json_data).await }, move |ui, result| {
+        match result {
+            Ok(_) => load_comments(ui, target),
+            Err(e) => show_retry_error(ui, e, move |ui| save_comment(ui, target, comment_id, content.clone())),
+        }
+    });
+}
+
+fn remove_comment(ui: &mut Cursive, target: CommentTarget, comment_id: i64) {
+    fetch(ui, |client| async move { client.delete_comment(comment_id).await }, move |ui, result| {
+        match result {
+            Ok(_) => load_comments(ui, target),
+            Err(e) => show_retry_error(ui, e, move |ui| remove_comment(ui, target, comment_id)),
+        }
+    });
+}
+
+/// Report a failed request, offering what makes sense for that kind of failure
+fn show_error(ui: &mut Cursive, error: CotoError) {
+    error_dialog(ui, error, None);
+}
+
+/// Report a failed request that `retry` can send again
+fn show_retry_error(ui: &mut Cursive, error: CotoError, retry: impl Fn(&mut Cursive) + 'static) {
+    error_dialog(ui, error, Some(Rc::new(retry)));
+}
+
+fn error_dialog(ui: &mut Cursive, error: CotoError, retry: Option<Action>) {
+    let title = match error {
+        CotoError::Auth => "Invalid API key",
+        CotoError::NotFound => "Not found",
+        CotoError::RateLimited { .. } => "Slow down",
+        CotoError::Server { .. } => "Todoist is having trouble",
+        CotoError::Network(_) => "Offline",
+        _ => "Error",
+    };
+
+    let mut dialog = Dialog::text(error.to_string()).title(title);
+    match (&error, retry) {
+        // Nothing will work until the key is replaced
+        (CotoError::Auth, _) => {}
+        // Whatever was selected is stale, so reload from the top
+        (CotoError::NotFound, _) => dialog.add_button("Go back", |ui| {
+            ui.pop_layer();
+            project_overview(ui);
+        }),
+        (e, Some(retry)) if e.is_transient() => {
+            dialog.add_button("Retry", move |ui| {
+                ui.pop_layer();
+                retry(ui);
+            });
+            dialog.add_button("Go back", |ui| { ui.pop_layer(); });
+        }
+        _ => dialog.add_button("Go back", |ui| { ui.pop_layer(); }),
+    }
+    dialog.add_button("Quit", |ui| ui.quit());
+    ui.add_layer(dialog);
+}
+
+/// Run a request on the shared runtime without holding up the UI, then
+/// hand its result to `then` back on the UI thread
+fn fetch<T, F>(ui: &mut Cursive, request: impl FnOnce(TodoistClient) -> F,
+    then: impl FnOnce(&mut Cursive, T) + Send + 'static)
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    fetch_or(ui, request, then, |_| {});
+}
+
+/// Like `fetch`, but if the request panics `recover` runs instead of
+/// `then`, before the crash is reported
+fn fetch_or<T, F>(ui: &mut Cursive, request: impl FnOnce(TodoistClient) -> F,
+    then: impl FnOnce(&mut Cursive, T) + Send + 'static, recover: impl FnOnce(&mut Cursive) + Send + 'static)
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let sink = ui.cb_sink().clone();
+    let App { client, runtime, loading, .. } = app(ui);
+    // Spawned on its own, so a panic comes back as an Err from its handle
+    let request = runtime.spawn(request(client.clone()));
+    *loading += 1;
+    runtime.spawn(async move {
+        let result = request.await;
+        // This only fails once the UI has quit, when nobody wants the result
+        let _ = sink.send(Box::new(move |ui: &mut Cursive| {
+            app(ui).loading -= 1;
+            match result {
+                Ok(result) => {
+                    show_status(ui);
+                    then(ui, result);
+                }
+                Err(e) => {
+                    recover(ui);
+                    show_status(ui);
+                    show_error(ui, CotoError::Crashed(panic_message(e)));
+                }
+            }
+        }));
+    });
+    show_status(ui);
+}
+
+/// What a background request panicked with, if it said
+fn panic_message(error: JoinError) -> String {
+    match error.try_into_panic() {
+        Ok(panic) => panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "it panicked".to_string()),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Apply a change to the store and queue it for Todoist. The next
+/// sync sends it, or keeps it queued while offline.
+fn mutate(ui: &mut Cursive, mutation: Mutation) -> Result<(), CotoError> {
+    let App { store, outbox, .. } = app(ui);
+    cache::record(store, outbox, mutation)
+}
+
+/// Send anything queued, then pull whatever changed since the last sync
+/// into the store, all in the background. The screen on top is drawn
+/// again if anything changed. Losing the connection just leaves the
+/// cached store.
+fn sync_in_background(ui: &mut Cursive) {
+    let app = app(ui);
+    if app.syncing {
+        app.sync_again = true;
+        return;
+    }
+    app.syncing = true;
+    flush_next(ui, FlushReport::default());
+}
+
+/// Send the oldest queued change, then carry on with the next one
+fn flush_next(ui: &mut Cursive, mut report: FlushReport) {
+    let App { outbox, deferred, .. } = app(ui);
+    let queued = match outbox.queue.first().cloned() {
+        // Put off until the next session, and sent in order, so nothing goes for now
+        Some(queued) if deferred.contains(&queued.request_id) => return pull_changes(ui, report),
+        Some(queued) => queued,
+        None => return pull_changes(ui, report),
+    };
+
+    let sent = queued.clone();
+    fetch_or(ui, |client| async move { sent.attempt(&client).await }, move |ui, attempt| {
+        let App { store, outbox, .. } = app(ui);
+        if outbox.settle_attempt(store, &queued, attempt, ConflictPolicy::Manual, &mut report) {
+            flush_next(ui, report);
+        } else {
+            pull_changes(ui, report);
+        }
+    }, abandon_sync);
+}
+
+fn pull_changes(ui: &mut Cursive, mut report: FlushReport) {
+    match report.stopped.take() {
+        Some(CotoError::Network(_)) => return finish_sync(ui, report, true, None),
+        Some(e) => return finish_sync(ui, report, false, Some(e)),
+        None => {}
+    }
+
+    let sync_token = store(ui).sync_token.to_string();
+    fetch_or(ui, |client| async move { client.sync(&sync_token).await }, move |ui, result| {
+        match result {
+            Ok(response) => {
+                if response.has_changes() {
+                    // Counted as sent so the screen is drawn again
+                    report.sent += 1;
+                }
+                let app = app(ui);
+                app.store.apply(response);
+                for queued in app.outbox.queue.iter() {
+                    app.store.apply_mutation(&queued.mutation);
+                }
+                let saved = app.store.save().err();
+                finish_sync(ui, report, false, saved);
+            }
+            Err(CotoError::Network(_)) => finish_sync(ui, report, true, None),
+            Err(e) => finish_sync(ui, report, false, Some(e)),
+        }
+    }, abandon_sync);
+}
+
+/// Give up on a sync whose request crashed, so the next one can start
+fn abandon_sync(ui: &mut Cursive) {
+    let app = app(ui);
+    app.syncing = false;
+    app.sync_again = false;
+}
+
+/// Redraw the screen if the sync changed anything, then report what went wrong
+fn finish_sync(ui: &mut Cursive, report: FlushReport, offline: bool, error: Option<CotoError>) {
+    let app = app(ui);
+    let changed = report.sent > 0 || app.offline != offline;
+    app.offline = offline;
+    app.syncing = false;
+    let again = std::mem::replace(&mut app.sync_again, false);
+
+    if changed {
+        redraw_screen(ui);
+    }
+    show_status(ui);
+
+    if !report.rejected.is_empty() {
+        let mut message = String::from("Todoist refused these offline changes, so they were dropped:\n");
+        for (queued, e) in report.rejected.iter() {
+            message.push_str(&format!("\n{:?}\n  {}", queued.mutation, e));
+        }
+        ui.add_layer(Dialog::info(message).title("Changes dropped"));
+    }
+    if let Some(conflict) = report.conflict {
+        conflict_dialog(ui, conflict);
+    }
+    if let Some(e) = error {
+        show_retry_error(ui, e, sync_in_background);
+    }
+
+    if again {
+        sync_in_background(ui);
+    }
+}
+
+/// Remember how to draw the screen that was just added
+fn set_screen(ui: &mut Cursive, redraw: impl Fn(&mut Cursive) + 'static) {
+    let layers = ui.screen().len();
+    app(ui).screen = Some(Screen { layers, redraw: Rc::new(redraw) });
+}
+
+/// Draw the last screen again, unless something else is open on top of it
+fn redraw_screen(ui: &mut Cursive) {
+    let screen = app(ui).screen.as_ref().map(|screen| (screen.layers, screen.redraw.clone()));
+    if let Some((layers, redraw)) = screen {
+        if ui.screen().len() == layers {
+            app(ui).search = ui.call_on_name("search", |bar: &mut EditView| bar.get_content().to_string());
+            redraw(ui);
+        }
+    }
+}
+
+/// One row of a searchable list. Only `text` is matched and highlighted,
+/// `suffix` follows it as it is.
+struct Entry {
+    text: String,
+    suffix: String,
+    id: i64,
+}
+
+/// The lists on a screen that `/` filters, by name, each with every entry
+/// it holds unfiltered, and the result `n` and `N` move on from
+struct Search {
+    lists: Vec<(String, Vec<Entry>)>,
+    cursor: Rc<Cell<(usize, usize)>>,
+}
+
+impl Search {
+    fn new() -> Self {
+        Search { lists: Vec::new(), cursor: Rc::new(Cell::new((0, 0))) }
+    }
+
+    /// Fill `select` with the `entries` that match `query` and name it, so
+    /// the search can filter it again and follow what is selected in it
+    fn list(&mut self, name: &str, mut select: SelectView<i64>, entries: Vec<Entry>, query: &str)
+        -> NamedView<SelectView<i64>> {
+        fill_list(&mut select, &entries, query);
+
+        let index = self.lists.len();
+        let cursor = self.cursor.clone();
+        let list_name = name.to_string();
+        select.set_on_select(move |ui, _| {
+            let selected = ui.call_on_name(&list_name, |list: &mut SelectView<i64>| list.selected_id());
+            if let Some(Some(item)) = selected {
+                cursor.set((index, item));
+            }
         });
-        task_counter += 1;
+        self.lists.push((name.to_string(), entries));
+        select.with_name(name)
     }
+}
+
+/// Refill `select` with the entries `query` matches
+fn fill_list(select: &mut SelectView<i64>, entries: &[Entry], query: &str) {
+    select.clear();
+    for entry in entries.iter() {
+        if let Some(positions) = search::fuzzy_match(query, &entry.text) {
+            select.add_item(highlight(entry, &positions), entry.id);
+        }
+    }
+}
+
+/// An entry's label with the matched characters in bold title colours
+fn highlight(entry: &Entry, positions: &[usize]) -> StyledString {
+    let style = Style::from(ColorStyle::title_primary()).combine(Effect::Bold);
+    let mut label = StyledString::new();
+    let mut run = String::new();
+    let mut matched = false;
+    for (i, c) in entry.text.chars().enumerate() {
+        if positions.contains(&i) != matched {
+            append_run(&mut label, &run, matched, style);
+            run.clear();
+            matched = !matched;
+        }
+        run.push(c);
+    }
+    append_run(&mut label, &run, matched, style);
+    label.append_plain(&entry.suffix);
+    label
+}
+
+fn append_run(label: &mut StyledString, run: &str, matched: bool, style: Style) {
+    if run.is_empty() {
+        return;
+    }
+    if matched {
+        label.append_styled(run, style);
+    } else {
+        label.append_plain(run);
+    }
+}
+
+/// Where the search bar goes when `/` opens it
+fn search_slot() -> NamedView<LinearLayout> {
+    LinearLayout::vertical().with_name("search_slot")
+}
+
+/// Open the search bar holding `query`, or move back into it if it is open
+fn open_search(ui: &mut Cursive, search: &Rc<Search>, query: &str) {
+    let filter = search.clone();
+    let submit = search.clone();
+    let bar = EditView::new()
+        .content(query)
+        .on_edit(move |ui, query, _| filter_lists(ui, &filter, query))
+        .on_submit(move |ui, _| select_result(ui, &submit, 0))
+        .with_name("search")
+        .min_width(20);
+    ui.call_on_name("search_slot", |slot: &mut LinearLayout| {
+        if slot.is_empty() {
+            slot.add_child(LinearLayout::horizontal()
+                .child(TextView::new("/"))
+                .child(bar));
+        }
+    });
+    ui.focus_name("search").ok();
+}
+
+/// Drop the search bar and show every entry again
+fn close_search(ui: &mut Cursive, search: &Search) {
+    filter_lists(ui, search, "");
+    ui.call_on_name("search_slot", |slot: &mut LinearLayout| slot.remove_child(0));
+    select_result(ui, search, 0);
+}
+
+fn filter_lists(ui: &mut Cursive, search: &Search, query: &str) {
+    for (name, entries) in search.lists.iter() {
+        ui.call_on_name(name, |list: &mut SelectView<i64>| fill_list(list, entries, query));
+    }
+    search.cursor.set((0, 0));
+}
+
+/// Select the `n`th result counted across every list and focus it
+fn select_result(ui: &mut Cursive, search: &Search, mut n: usize) {
+    for (index, (name, _)) in search.lists.iter().enumerate() {
+        let len = ui.call_on_name(name, |list: &mut SelectView<i64>| list.len()).unwrap_or(0);
+        if n < len {
+            if let Some(callback) = ui.call_on_name(name, |list: &mut SelectView<i64>| list.set_selection(n)) {
+                callback(ui);
+            }
+            ui.focus_name(name).ok();
+            search.cursor.set((index, n));
+            return;
+        }
+        n -= len;
+    }
+}
+
+/// Move to the next result, or the previous one, wrapping around at the ends
+fn step_result(ui: &mut Cursive, search: &Search, forward: bool) {
+    let counts: Vec<usize> = search.lists.iter()
+        .map(|(name, _)| ui.call_on_name(name, |list: &mut SelectView<i64>| list.len()).unwrap_or(0))
+        .collect();
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return;
+    }
+
+    let (list, item) = search.cursor.get();
+    let current = counts.iter().take(list).sum::<usize>() + item;
+    let next = if forward { (current + 1) % total } else { (current + total - 1) % total };
+    select_result(ui, search, next);
+}
+
+/// What is happening in the background, for the status line under the controls
+fn status_text(ui: &mut Cursive) -> &'static str {
+    let app = app(ui);
+    if app.syncing {
+        "\nSyncing..."
+    } else if app.loading > 0 {
+        "\nLoading..."
+    } else {
+        ""
+    }
+}
+
+fn status_view(ui: &mut Cursive) -> NamedView<TextView> {
+    TextView::new(status_text(ui)).with_name("status")
+}
+
+fn show_status(ui: &mut Cursive) {
+    let text = status_text(ui);
+    ui.call_on_name("status", |view: &mut TextView| view.set_content(text));
+}
+
+/// Ask how to settle an offline edit that clashes with Todoist
+fn conflict_dialog(ui: &mut Cursive, conflict: Conflict) {
+    let mut summary = format!("\"{}\" was changed here and on Todoist since the last sync.\n", conflict.server.content);
+    for field_conflict in conflict.fields.iter() {
+        summary.push_str(&format!("\n{}: was {}, yours {}, Todoist's {}",
+            field_conflict.field.name(), field_conflict.base,
+            field_conflict.local, field_conflict.server));
+    }
+
+    let deferred = app(ui).outbox.queue.first().map(|queued| queued.request_id);
+    let server_conflict = conflict.clone();
+    let local_conflict = conflict.clone();
+    ui.add_layer(Dialog::text(summary)
+        .title("Conflict")
+        .button("Keep Todoist's", move |ui| settle_conflict(ui, &server_conflict, ConflictPolicy::Server, &[]))
+        .button("Keep mine", move |ui| settle_conflict(ui, &local_conflict, ConflictPolicy::Local, &[]))
+        .button("Merge", move |ui| {
+            ui.pop_layer();
+            merge_dialog(ui, conflict.clone());
+        })
+        .button("Later", move |ui| {
+            ui.pop_layer();
+            app(ui).deferred.extend(deferred);
+        }));
+}
+
+/// Pick a side for each clashing field
+fn merge_dialog(ui: &mut Cursive, conflict: Conflict) {
+    let mut fields = LinearLayout::vertical();
+    let mut choices = Vec::new();
+    for field_conflict in conflict.fields.iter() {
+        let mut group: RadioGroup<bool> = RadioGroup::new();
+        fields.add_child(TextView::new(format!("\n{} (was {})", field_conflict.field.name(), field_conflict.base)));
+        fields.add_child(group.button(true, format!("Mine: {}", field_conflict.local)));
+        fields.add_child(group.button(false, format!("Todoist's: {}", field_conflict.server)).selected());
+        choices.push((field_conflict.field, group));
+    }
+
+    ui.add_layer(Dialog::around(fields.scrollable())
+        .title("Merge")
+        .button("Apply", move |ui| {
+            let keep_local: Vec<Field> = choices.iter()
+                .filter(|(_, group)| *group.selection())
+                .map(|(field, _)| *field)
+                .collect();
+            settle_conflict(ui, &conflict, ConflictPolicy::Manual, &keep_local);
+        })
+        .button("Cancel", |ui| { ui.pop_layer(); }));
+}
+
+fn settle_conflict(ui: &mut Cursive, conflict: &Conflict, policy: ConflictPolicy, keep_local: &[Field]) {
+    ui.pop_layer();
+    if let Err(e) = conflict::settle(&mut app(ui).outbox, conflict, policy, keep_local) {
+        return show_error(ui, e);
+    }
+    project_overview(ui);
+}
+
+/// What to call a project in screen titles
+fn project_name(ui: &mut Cursive, project_id: i64) -> String {
+    match store(ui).projects.get(&project_id) {
+        Some(project) if project.inbox_project => "Inbox".to_string(),
+        Some(project) => project.name.to_string(),
+        None => "Tasks".to_string(),
+    }
+}
+
+/// A screen title, noting when coto is working from the local cache
+fn screen_title(ui: &mut Cursive, title: &str) -> String {
+    let app = app(ui);
+    if app.offline {
+        format!("{} (offline, {} queued)", title, app.outbox.len())
+    } else if !app.outbox.is_empty() {
+        format!("{} ({} queued)", title, app.outbox.len())
+    } else {
+        title.to_string()
+    }
+}
+
+fn app(ui: &mut Cursive) -> &mut App {
+    ui.user_data::<App>().expect("App state is set in main")
+}
+
+fn store(ui: &mut Cursive) -> &mut Store {
+    &mut app(ui).store
+}
+
+fn gather_projects(ui: &mut Cursive) -> Vec<Project> {
+    store(ui).projects()
+}
+
+fn gather_sections(ui: &mut Cursive, id: i64) -> Vec<Section> {
+    store(ui).project_sections(id)
+}
+
+fn gather_all_tasks(ui: &mut Cursive) -> Vec<Task> {
+    store(ui).tasks()
+}
+
+fn gather_tasks(ui: &mut Cursive, project_id: i64) -> Vec<Task> {
+    store(ui).project_tasks(project_id)
+}
 
-    Ok(task_vec)
+fn gather_labels(ui: &mut Cursive) -> Vec<Label> {
+    store(ui).labels()
 }
@@ -0,0 +1,131 @@
+// ##### Todoist Data Model #####
+
+// These are the types that the Todoist
+// REST API responses are deserialized
+// into. The TUI and any scripts share
+// these as their one data model.
+
+use serde::{Serialize, Deserialize};
+
+/// A Todoist project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub color: i64,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub order: i64,
+    #[serde(default)]
+    pub comment_count: i64,
+    #[serde(default)]
+    pub shared: bool,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub inbox_project: bool,
+    #[serde(default)]
+    pub team_inbox: bool,
+}
+
+/// A section within a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Section {
+    pub id: i64,
+    pub project_id: i64,
+    #[serde(default)]
+    pub order: i64,
+    pub name: String,
+}
+
+/// A task, optionally within a section (a `section_id` of 0 means none)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Task {
+    pub id: i64,
+    pub project_id: i64,
+    #[serde(default)]
+    pub section_id: i64,
+    pub content: String,
+    #[serde(default)]
+    pub completed: bool,
+    #[serde(default)]
+    pub label_ids: Vec<i64>,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub order: i64,
+    #[serde(default = "default_priority")]
+    pub priority: i64,
+    #[serde(default)]
+    pub due: Option<Due>,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub comment_count: i64,
+    #[serde(default)]
+    pub created: String,
+}
+
+/// The due date of a task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Due {
+    pub string: String,
+    pub date: String,
+    #[serde(default)]
+    pub recurring: bool,
+    #[serde(default)]
+    pub datetime: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+/// A personal label
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Label {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub color: i64,
+    #[serde(default)]
+    pub order: i64,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+/// A comment on either a task or a project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub id: i64,
+    #[serde(default)]
+    pub task_id: Option<i64>,
+    #[serde(default)]
+    pub project_id: Option<i64>,
+    pub content: String,
+    #[serde(default)]
+    pub posted: String,
+}
+
+/// A collaborator on a shared project
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub struct Collaborator {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+}
+
+/// The colors Todoist lets projects and labels take, by id
+pub const COLORS: &[(i64, &str)] = &[
+    (30, "Berry red"), (31, "Red"), (32, "Orange"), (33, "Yellow"),
+    (34, "Olive green"), (35, "Lime green"), (36, "Green"), (37, "Mint green"),
+    (38, "Teal"), (39, "Sky blue"), (40, "Light blue"), (41, "Blue"),
+    (42, "Grape"), (43, "Violet"), (44, "Lavender"), (45, "Magenta"),
+    (46, "Salmon"), (47, "Charcoal"), (48, "Grey"), (49, "Taupe"),
+];
+
+/// Todoist priorities run from 1 (normal) to 4 (urgent)
+fn default_priority() -> i64 {
+    1
+}
@@ -0,0 +1,286 @@
+// ##### Output Formats #####
+
+// These are the functions that print
+// listings for scripts and people. The
+// column names are the model's field
+// names, so they stay the same across
+// JSON, CSV, TSV and the plain table.
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::model::{Project, Section, Task};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Tsv,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "table" => Ok(Format::Table),
+            other => Err(format!("Unknown format `{}` (use json, csv, tsv or table)", other)),
+        }
+    }
+}
+
+/// Something that can be listed as one row of flat columns
+pub trait Row: Serialize {
+    fn columns() -> &'static [&'static str];
+    fn row(&self) -> Vec<String>;
+}
+
+impl Row for Project {
+    fn columns() -> &'static [&'static str] {
+        &["id", "name", "color", "parent_id", "order", "comment_count",
+            "shared", "favorite", "inbox_project", "team_inbox"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.to_string(),
+            self.color.to_string(),
+            optional(&self.parent_id),
+            self.order.to_string(),
+            self.comment_count.to_string(),
+            self.shared.to_string(),
+            self.favorite.to_string(),
+            self.inbox_project.to_string(),
+            self.team_inbox.to_string(),
+        ]
+    }
+}
+
+impl Row for Section {
+    fn columns() -> &'static [&'static str] {
+        &["id", "project_id", "order", "name"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.project_id.to_string(),
+            self.order.to_string(),
+            self.name.to_string(),
+        ]
+    }
+}
+
+impl Row for Task {
+    fn columns() -> &'static [&'static str] {
+        &["id", "project_id", "section_id", "content", "completed", "label_ids",
+            "parent_id", "order", "priority", "due_date", "due_datetime",
+            "due_string", "due_recurring", "url", "comment_count", "created"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let label_ids: Vec<String> = self.label_ids.iter().map(|id| id.to_string()).collect();
+        let due = self.due.as_ref();
+        vec![
+            self.id.to_string(),
+            self.project_id.to_string(),
+            self.section_id.to_string(),
+            self.content.to_string(),
+            self.completed.to_string(),
+            label_ids.join(","),
+            optional(&self.parent_id),
+            self.order.to_string(),
+            self.priority.to_string(),
+            due.map_or(String::new(), |due| due.date.to_string()),
+            due.and_then(|due| due.datetime.clone()).unwrap_or_default(),
+            due.map_or(String::new(), |due| due.string.to_string()),
+            due.map_or(String::new(), |due| due.recurring.to_string()),
+            self.url.to_string(),
+            self.comment_count.to_string(),
+            self.created.to_string(),
+        ]
+    }
+}
+
+/// Render a listing with a header row, or a JSON array
+pub fn render<T: Row>(items: &[T], format: Format) -> String {
+    match format {
+        Format::Json => {
+            let mut json = serde_json::to_string_pretty(items).expect("Models always serialize");
+            json.push('\n');
+            json
+        }
+        Format::Csv => delimited(items, ",", csv_field),
+        Format::Tsv => delimited(items, "\t", tsv_field),
+        Format::Table => table(items),
+    }
+}
+
+pub fn print<T: Row>(items: &[T], format: Format) {
+    print!("{}", render(items, format));
+}
+
+fn delimited<T: Row>(items: &[T], separator: &str, escape: fn(&str) -> String) -> String {
+    let mut out = T::columns().join(separator);
+    out.push('\n');
+    for item in items.iter() {
+        let fields: Vec<String> = item.row().iter().map(|field| escape(field)).collect();
+        out.push_str(&fields.join(separator));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote a CSV field if it holds a comma, quote or line break (RFC 4180)
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// TSV has no quoting, so tabs and line breaks are escaped instead
+fn tsv_field(field: &str) -> String {
+    field.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn table<T: Row>(items: &[T]) -> String {
+    let header: Vec<String> = T::columns().iter().map(|column| column.to_string()).collect();
+    let rows: Vec<Vec<String>> = std::iter::once(header)
+        .chain(items.iter().map(|item| item.row().iter().map(|field| tsv_field(field)).collect()))
+        .collect();
+
+    let mut widths = vec![0; T::columns().len()];
+    for row in rows.iter() {
+        for (width, field) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in rows.iter() {
+        let padded: Vec<String> = row.iter().zip(widths.iter())
+            .map(|(field, width)| format!("{:width$}", field, width = width))
+            .collect();
+        out.push_str(padded.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A task listed by a saved view, with the heading of the group it was
+/// listed under. A task can show up in more than one group (once per
+/// label), and this tells those rows apart.
+#[derive(Serialize)]
+pub struct GroupedTask {
+    pub group: String,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+impl Row for GroupedTask {
+    fn columns() -> &'static [&'static str] {
+        &["group", "id", "project_id", "section_id", "content", "completed", "label_ids",
+            "parent_id", "order", "priority", "due_date", "due_datetime",
+            "due_string", "due_recurring", "url", "comment_count", "created"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        std::iter::once(self.group.to_string())
+            .chain(self.task.row())
+            .collect()
+    }
+}
+
+fn optional(value: &Option<i64>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Section;
+    use crate::test_support::{due_at, labelled, task};
+
+    fn section(id: i64, name: &str) -> Section {
+        Section { id, project_id: 1, order: id, name: name.to_string() }
+    }
+
+    #[test]
+    fn csv_quotes_commas_quotes_and_line_breaks() {
+        let sections = vec![
+            section(1, "Plain"),
+            section(2, "Eggs, milk"),
+            section(3, "The \"big\" one"),
+            section(4, "Two\nlines"),
+        ];
+
+        assert_eq!(render(&sections, Format::Csv), "id,project_id,order,name\n\
+            1,1,1,Plain\n\
+            2,1,2,\"Eggs, milk\"\n\
+            3,1,3,\"The \"\"big\"\" one\"\n\
+            4,1,4,\"Two\nlines\"\n");
+    }
+
+    #[test]
+    fn tsv_escapes_tabs_and_line_breaks() {
+        let sections = vec![section(1, "Tab\there"), section(2, "Two\r\nlines"), section(3, "C:\\notes")];
+
+        assert_eq!(render(&sections, Format::Tsv), "id\tproject_id\torder\tname\n\
+            1\t1\t1\tTab\\there\n\
+            2\t1\t2\tTwo\\r\\nlines\n\
+            3\t1\t3\tC:\\\\notes\n");
+    }
+
+    #[test]
+    fn table_pads_columns_to_the_widest_field() {
+        let sections = vec![section(1, "Next"), section(12, "Waiting on others")];
+
+        assert_eq!(render(&sections, Format::Table), "\
+            id  project_id  order  name\n\
+            1   1           1      Next\n\
+            12  1           12     Waiting on others\n");
+    }
+
+    #[test]
+    fn task_row_lines_up_with_its_columns() {
+        let task = labelled(due_at(task(3, 1, "Call mum"), "2026-10-20", "2026-10-20T17:00:00"), &[7, 8]);
+        let row = task.row();
+
+        assert_eq!(row.len(), Task::columns().len());
+        let field = |column: &str| &row[Task::columns().iter().position(|c| *c == column).unwrap()];
+        assert_eq!(field("label_ids"), "7,8");
+        assert_eq!(field("due_date"), "2026-10-20");
+        assert_eq!(field("due_datetime"), "2026-10-20T17:00:00");
+        assert_eq!(field("parent_id"), "");
+    }
+
+    #[test]
+    fn grouped_task_leads_with_its_group() {
+        let rows = vec![
+            GroupedTask { group: "home".to_string(), task: labelled(task(3, 1, "Call mum"), &[7, 8]) },
+            GroupedTask { group: "phone".to_string(), task: labelled(task(3, 1, "Call mum"), &[7, 8]) },
+        ];
+
+        let csv = render(&rows, Format::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].starts_with("group,id,project_id,"));
+        assert!(lines[1].starts_with("home,3,1,"));
+        assert!(lines[2].starts_with("phone,3,1,"));
+        assert_eq!(rows[0].row().len(), GroupedTask::columns().len());
+
+        let json: serde_json::Value = serde_json::from_str(&render(&rows, Format::Json)).unwrap();
+        assert_eq!(json[1]["group"], "phone");
+        assert_eq!(json[1]["content"], "Call mum");
+    }
+}
@@ -1,329 +1,572 @@
 // ###### Todoist REST API Functions #####
 
 // These are the functions that are used
-// to make queries to the Todoist REST API
+// to make queries to the Todoist REST API.
+// They all hang off one TodoistClient so
+// the token, connection pool and base URL
+// are shared between every request.
+// Calls nothing uses yet are kept too,
+// so that the whole API stays covered.
 
 
-use reqwest::{header, Client, Url};
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::{header, Client, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+use tokio::time::delay_for;
 use uuid::Uuid;
 
-// ##### Projects #####
+use crate::config::{Config, Retry};
+use crate::error::CotoError;
+use crate::model::{Project, Section, Task, Label, Comment, Collaborator};
+use crate::sync::{SyncResponse, RESOURCE_TYPES};
+
+/// The Todoist REST API used when nothing else is configured
+pub const DEFAULT_BASE_URL: &str = "https://api.todoist.com/rest/v1";
+
+/// Environment variable that overrides the configured base URL
+pub const BASE_URL_VAR: &str = "COTO_BASE_URL";
 
-pub async fn get_all_projects(token: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+/// The Todoist Sync API endpoint used when nothing else is configured
+pub const DEFAULT_SYNC_URL: &str = "https://api.todoist.com/sync/v8/sync";
 
-    let res = Client::new()
-        .get("https://api.todoist.com/rest/v1/projects")
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+/// Environment variable that overrides the configured sync URL
+pub const SYNC_URL_VAR: &str = "COTO_SYNC_URL";
 
-    Ok(res)
+/// Holds the API token (as a default header), a pooled HTTP client and the base URL
+#[derive(Clone)]
+pub struct TodoistClient {
+    base_url: String,
+    sync_url: String,
+    client: Client,
+    retry: Retry,
+    request_id: Option<Uuid>,
 }
 
-pub async fn get_project(token: &String, id: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+impl TodoistClient {
+    pub fn new(token: &str, base_url: &str) -> Self {
+        let mut headers = header::HeaderMap::new();
+        headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .expect("Could not build HTTP client");
+
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            sync_url: DEFAULT_SYNC_URL.to_string(),
+            client,
+            retry: Retry::default(),
+            request_id: None,
+        }
+    }
+
+    pub fn with_sync_url(mut self, sync_url: &str) -> Self {
+        self.sync_url = sync_url.to_string();
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Build a client from the config, letting `COTO_BASE_URL` and
+    /// `COTO_SYNC_URL` take precedence
+    pub fn from_config(config: &Config) -> Self {
+        let base_url = std::env::var(BASE_URL_VAR).ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| config.base_url.clone())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        let sync_url = std::env::var(SYNC_URL_VAR).ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| config.sync_url.clone())
+            .unwrap_or_else(|| DEFAULT_SYNC_URL.to_string());
+
+        Self::new(&config.todoist_key, &base_url)
+            .with_sync_url(&sync_url)
+            .with_retry(config.retry.clone())
+    }
+
+    /// A copy of this client that sends `request_id` as the X-Request-Id of
+    /// every change, so replaying a queued change can never apply it twice
+    pub fn with_request_id(&self, request_id: Uuid) -> Self {
+        Self {
+            request_id: Some(request_id),
+            ..self.clone()
+        }
+    }
+
+    fn request_id(&self) -> Uuid {
+        self.request_id.unwrap_or_else(Uuid::new_v4)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Send a request, retrying rate limits and server errors with backoff.
+    /// Every attempt is a clone of `request`, so they all carry the same
+    /// X-Request-Id and Todoist will only ever apply a create once.
+    async fn send(&self, request: RequestBuilder) -> Result<String, CotoError> {
+        let mut attempt: u32 = 0;
+        loop {
+            let attempt_request = request.try_clone()
+                .expect("Request bodies are always buffered");
+
+            match self.send_once(attempt_request).await {
+                // A Retry-After past `max_delay_ms` is handed back rather than waited out
+                Err(CotoError::RateLimited { retry_after }) if attempt < self.retry.max_retries
+                    && retry_after.is_none_or(|secs| secs.saturating_mul(1000) <= self.retry.max_delay_ms) => {
+                    delay_for(backoff(&self.retry, attempt, retry_after)).await;
+                }
+                Err(CotoError::Server { .. }) if attempt < self.retry.max_retries => {
+                    delay_for(backoff(&self.retry, attempt, None)).await;
+                }
+                result => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Send a request once, turning any unsuccessful status into a CotoError
+    async fn send_once(&self, request: RequestBuilder) -> Result<String, CotoError> {
+        let res = request.send().await?;
+        let status = res.status();
+        let retry_after = res.headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        let body = res.text().await?;
+
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(CotoError::from_status(status, retry_after, body))
+        }
+    }
+
+    // ##### Sync #####
+
+    /// Everything that changed since `sync_token`, or everything for `sync::FULL_SYNC`
+    pub async fn sync(&self, sync_token: &str) -> Result<SyncResponse, CotoError> {
+        let request = self.client
+            .post(&self.sync_url)
+            .form(&[("sync_token", sync_token), ("resource_types", RESOURCE_TYPES)]);
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    /// Run one Sync API command, for the changes REST cannot make. The
+    /// command uuid is the request id, so Todoist only ever applies it once.
+    async fn command(&self, command_type: &str, args: Value) -> Result<(), CotoError> {
+        let uuid = self.request_id();
+        let commands = json!([{"type": command_type, "uuid": uuid, "args": args}]).to_string();
+
+        let request = self.client
+            .post(&self.sync_url)
+            .form(&[("commands", commands.as_str())]);
+
+        let res: Value = serde_json::from_str(&self.send(request).await?)?;
+        match &res["sync_status"][uuid.to_string()] {
+            Value::String(status) if status == "ok" => Ok(()),
+            status => {
+                let code = status["http_code"].as_u64().unwrap_or(400) as u16;
+                let message = status["error"].as_str().unwrap_or("the command was not applied").to_string();
+                Err(CotoError::from_status(StatusCode::from_u16(code).unwrap_or(StatusCode::BAD_REQUEST), None, message))
+            }
+        }
+    }
+
+    /// Move a task to the section or project named in `json_data`
+    pub async fn move_task(&self, id: i64, mut json_data: Value) -> Result<(), CotoError> {
+        json_data["id"] = Value::from(id);
+        self.command("item_move", json_data).await
+    }
+
+    /// Give each task in `orders` its new position, as (id, order) pairs
+    pub async fn reorder_tasks(&self, orders: &[(i64, i64)]) -> Result<(), CotoError> {
+        let items: Vec<Value> = orders.iter()
+            .map(|(id, order)| json!({"id": id, "child_order": order}))
+            .collect();
+        self.command("item_reorder", json!({"items": items})).await
+    }
+
+    // ##### Projects #####
+
+    pub async fn get_all_projects(&self) -> Result<Vec<Project>, CotoError> {
+        let request = self.client
+            .get(&self.url("projects"));
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_project(&self, id: i64) -> Result<Project, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("projects/{}", id)));
+
+        let res = self.send(request).await?;
 
-    let res = Client::new()
-        .get(Url::parse(&format!("https://api.todoist.com/rest/v1/projects/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    pub async fn new_project(&self, json_name: String) -> Result<Project, CotoError> {
+        let uuid = self.request_id();
 
-    Ok(res)
-}
+        let request = self.client
+            .post(&self.url("projects"))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_name);
 
+        let res = self.send(request).await?;
 
-pub async fn new_project(token: &String, json_name: String) -> Result<(), Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("X-Request-Id", uuid.to_string().parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        Ok(serde_json::from_str(&res)?)
+    }
 
-    let _res = Client::new()
-        .post("https://api.todoist.com/rest/v1/projects")
-        .headers(headers)
-        .body(json_name)
-        .send()
-        .await?
-        .text()
-        .await?;
+    pub async fn update_project(&self, id: i64, json_data: String) -> Result<(), CotoError> {
+        let uuid = self.request_id();
 
-    Ok(())
-}
+        let request = self.client
+            .post(&self.url(&format!("projects/{}", id)))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
 
-pub async fn update_project(token: &String, id: &String, json_data: String) -> Result<(), Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("X-Request-Id", uuid.to_string().parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
-
-    let _res = Client::new()
-        .post(Url::parse(&format!("https://api.todoist.com/rest/v1/projects/{}", id)).unwrap())
-        .headers(headers)
-        .body(json_data)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(())
-}
+        self.send(request).await?;
 
-pub async fn delete_project(token: &String, id: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        Ok(())
+    }
 
-    let _res = Client::new()
-        .delete(Url::parse(&format!("https://api.todoist.com/rest/v1/projects/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    pub async fn delete_project(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .delete(&self.url(&format!("projects/{}", id)));
 
-    Ok(())
-}
+        self.send(request).await?;
 
-// ##### Collaborators #####
+        Ok(())
+    }
 
-pub async fn get_collaborators(token: &String, id: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    // ##### Collaborators #####
 
-    let res = Client::new()
-        .get(Url::parse(&format!("https://api.todoist.com/rest/v1/projects/{}/collaborators", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    #[allow(dead_code)]
+    pub async fn get_collaborators(&self, id: i64) -> Result<Vec<Collaborator>, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("projects/{}/collaborators", id)));
 
-    Ok(res)
-}
+        let res = self.send(request).await?;
 
-// ##### Sections #####
+        Ok(serde_json::from_str(&res)?)
+    }
 
-pub async fn get_all_sections(token: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    // ##### Sections #####
 
-    let res = Client::new()
-        .get("https://api.todoist.com/rest/v1/sections")
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    #[allow(dead_code)]
+    pub async fn get_all_sections(&self) -> Result<Vec<Section>, CotoError> {
+        let request = self.client
+            .get(&self.url("sections"));
 
-    Ok(res)
-}
+        let res = self.send(request).await?;
 
-pub async fn get_project_sections(token: &String, id: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        Ok(serde_json::from_str(&res)?)
+    }
 
-    let res = Client::new()
-        .get(Url::parse(&format!("https://api.todoist.com/rest/v1/sections?project_id={}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    pub async fn get_project_sections(&self, id: i64) -> Result<Vec<Section>, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("sections?project_id={}", id)));
 
-    Ok(res)
-}
+        let res = self.send(request).await?;
 
-pub async fn get_section(token: &String, id: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        Ok(serde_json::from_str(&res)?)
+    }
 
-    let res = Client::new()
-        .get(Url::parse(&format!("https://api.todoist.com/rest/v1/sections/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    #[allow(dead_code)]
+    pub async fn get_section(&self, id: i64) -> Result<Section, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("sections/{}", id)));
 
-    Ok(res)
-}
+        let res = self.send(request).await?;
 
-pub async fn new_section(token: &String, json_name_and_id: String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
-
-    let _res = Client::new()
-        .post("https://api.todoist.com/rest/v1/sections")
-        .headers(headers)
-        .body(json_name_and_id)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(())
-}
+        Ok(serde_json::from_str(&res)?)
+    }
 
+    pub async fn new_section(&self, json_name_and_id: String) -> Result<Section, CotoError> {
+        let uuid = self.request_id();
 
-pub async fn update_section(token: &String, id: &String, json_name: String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let request = self.client
+            .post(&self.url("sections"))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_name_and_id);
 
-    let _res = Client::new()
-        .post(Url::parse(&format!("https://api.todoist.com/rest/v1/sections/{}", id)).unwrap())
-        .headers(headers)
-        .body(json_name)
-        .send()
-        .await?
-        .text()
-        .await?;
+        let res = self.send(request).await?;
 
-    Ok(())
-}
+        Ok(serde_json::from_str(&res)?)
+    }
 
-pub async fn delete_section(token: &String, id: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    pub async fn update_section(&self, id: i64, json_name: String) -> Result<(), CotoError> {
+        let uuid = self.request_id();
 
-    let _res = Client::new()
-        .delete(Url::parse(&format!("https://api.todoist.com/rest/v1/sections/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        let request = self.client
+            .post(&self.url(&format!("sections/{}", id)))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_name);
 
-    Ok(())
-}
+        self.send(request).await?;
 
-// ##### Tasks #####
+        Ok(())
+    }
 
-pub async fn get_all_tasks(token: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    pub async fn delete_section(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .delete(&self.url(&format!("sections/{}", id)));
 
-    let res = Client::new()
-        .get("https://api.todoist.com/rest/v1/tasks")
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        self.send(request).await?;
 
-    Ok(res)
-}
+        Ok(())
+    }
 
-pub async fn get_task(token: &String, id: &String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    // ##### Tasks #####
 
-    let res = Client::new()
-        .get(Url::parse(&format!("https://api.todoist.com/rest/v1/tasks/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+    /// Every active task, or only those matching a Todoist `filter` query
+    /// such as `(today | overdue) & #Work`, written in `lang` if not English
+    pub async fn get_all_tasks(&self, filter: Option<&str>, lang: Option<&str>) -> Result<Vec<Task>, CotoError> {
+        let mut params = Vec::new();
+        if let Some(filter) = filter {
+            params.push(("filter", filter));
+        }
+        if let Some(lang) = lang {
+            params.push(("lang", lang));
+        }
+        let request = self.client
+            .get(&self.url("tasks"))
+            .query(&params);
 
-    Ok(res)
-}
+        let res = self.send(request).await?;
 
-pub async fn new_task(token: &String, json_data: String) -> Result<(), Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("X-Request-Id", uuid.to_string().parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
-
-    let _res = Client::new()
-        .post("https://api.todoist.com/rest/v1/tasks")
-        .headers(headers)
-        .body(json_data)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(())
-}
+        Ok(serde_json::from_str(&res)?)
+    }
 
-pub async fn update_task(token: &String, id: &String, json_data: String) -> Result<std::string::String, Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("X-Request-Id", uuid.to_string().parse().unwrap());
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
-
-    let res = Client::new()
-        .post(Url::parse(&format!("https://api.todoist.com/rest/v1/tasks/{}", id)).unwrap())
-        .headers(headers)
-        .body(json_data)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(res)
-}
+    pub async fn get_project_tasks(&self, id: i64) -> Result<Vec<Task>, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("tasks?project_id={}", id)));
 
-pub async fn close_task(token: &String, id: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let res = self.send(request).await?;
 
-    let _res = Client::new()
-        .post(Url::parse(&format!("https://api.todoist.com/rest/v1/tasks/{}/close", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        Ok(serde_json::from_str(&res)?)
+    }
 
-    Ok(())
-}
+    pub async fn get_task(&self, id: i64) -> Result<Task, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("tasks/{}", id)));
 
-pub async fn reopen_task(token: &String, id: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let res = self.send(request).await?;
 
-    let _res = Client::new()
-        .post(Url::parse(&format!("https://api.todoist.com/rest/v1/tasks/{}/reopen", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        Ok(serde_json::from_str(&res)?)
+    }
 
-    Ok(())
-}
+    pub async fn new_task(&self, json_data: String) -> Result<Task, CotoError> {
+        let uuid = self.request_id();
 
-pub async fn delete_task(token: &String, id: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut headers = header::HeaderMap::new();
-    headers.insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let request = self.client
+            .post(&self.url("tasks"))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
 
-    let _res = Client::new()
-        .delete(Url::parse(&format!("https://api.todoist.com/rest/v1/tasks/{}", id)).unwrap())
-        .headers(headers)
-        .send()
-        .await?
-        .text()
-        .await?;
+        let res = self.send(request).await?;
 
-    Ok(())
-}
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    pub async fn update_task(&self, id: i64, json_data: String) -> Result<(), CotoError> {
+        let uuid = self.request_id();
+
+        let request = self.client
+            .post(&self.url(&format!("tasks/{}", id)))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn close_task(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .post(&self.url(&format!("tasks/{}/close", id)));
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub async fn reopen_task(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .post(&self.url(&format!("tasks/{}/reopen", id)));
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_task(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .delete(&self.url(&format!("tasks/{}", id)));
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    // ##### Comments #####
+
+    pub async fn get_task_comments(&self, task_id: i64) -> Result<Vec<Comment>, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("comments?task_id={}", task_id)));
+
+        let res = self.send(request).await?;
 
-// ##### Comments #####
-// ToDo
+        Ok(serde_json::from_str(&res)?)
+    }
 
-// ##### Labels #####
-// ToDo
+    pub async fn get_project_comments(&self, project_id: i64) -> Result<Vec<Comment>, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("comments?project_id={}", project_id)));
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_comment(&self, id: i64) -> Result<Comment, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("comments/{}", id)));
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    /// `json_data` needs the content and either a task_id or a project_id
+    pub async fn new_comment(&self, json_data: String) -> Result<Comment, CotoError> {
+        let uuid = self.request_id();
+
+        let request = self.client
+            .post(&self.url("comments"))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    pub async fn update_comment(&self, id: i64, json_data: String) -> Result<(), CotoError> {
+        let uuid = self.request_id();
+
+        let request = self.client
+            .post(&self.url(&format!("comments/{}", id)))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_comment(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .delete(&self.url(&format!("comments/{}", id)));
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    // ##### Labels #####
+
+    pub async fn get_all_labels(&self) -> Result<Vec<Label>, CotoError> {
+        let request = self.client
+            .get(&self.url("labels"));
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_label(&self, id: i64) -> Result<Label, CotoError> {
+        let request = self.client
+            .get(&self.url(&format!("labels/{}", id)));
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    pub async fn new_label(&self, json_data: String) -> Result<Label, CotoError> {
+        let uuid = self.request_id();
+
+        let request = self.client
+            .post(&self.url("labels"))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
+
+        let res = self.send(request).await?;
+
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    pub async fn update_label(&self, id: i64, json_data: String) -> Result<(), CotoError> {
+        let uuid = self.request_id();
+
+        let request = self.client
+            .post(&self.url(&format!("labels/{}", id)))
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", uuid.to_string())
+            .body(json_data);
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_label(&self, id: i64) -> Result<(), CotoError> {
+        let request = self.client
+            .delete(&self.url(&format!("labels/{}", id)));
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+}
+
+/// How long to wait before retry number `attempt` (counting from 0).
+/// A Retry-After from Todoist wins (`send` never waits one out past
+/// `max_delay_ms`), otherwise this is exponential backoff with full
+/// jitter, capped at `max_delay_ms`.
+fn backoff(retry: &Retry, attempt: u32, retry_after: Option<u64>) -> Duration {
+    if let Some(secs) = retry_after {
+        return Duration::from_secs(secs);
+    }
+
+    let ceiling = retry.base_delay_ms
+        .saturating_mul(1u64 << attempt.min(32))
+        .min(retry.max_delay_ms);
+    Duration::from_millis(rand::thread_rng().gen_range(0, ceiling + 1))
+}
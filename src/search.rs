@@ -0,0 +1,88 @@
+// ##### Search #####
+
+// This is the matching behind the `/`
+// search bar. A query matches when its
+// characters appear in order, ignoring
+// case, and the positions it matched are
+// handed back so they can be highlighted.
+
+/// The character positions in `text` that `query` matched, or `None` if it
+/// doesn't match. An empty query matches everything with nothing to highlight.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<Vec<usize>> {
+    let query = fold(query);
+    let text = fold(text);
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+
+    // A query typed out in one piece is highlighted as one piece,
+    // rather than scattered over the first letters that fit
+    if let Some(start) = text.windows(query.len()).position(|window| window == &query[..]) {
+        return Some((start..start + query.len()).collect());
+    }
+
+    let mut positions = Vec::new();
+    let mut wanted = query.iter().peekable();
+    for (i, c) in text.iter().enumerate() {
+        match wanted.peek() {
+            Some(next) if *next == c => {
+                positions.push(i);
+                wanted.next();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    if wanted.peek().is_none() {
+        Some(positions)
+    } else {
+        None
+    }
+}
+
+/// One lowercase character per character of `text`, so positions line up
+fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_is_ignored() {
+        assert_eq!(fuzzy_match("MILK", "Buy milk"), Some(vec![4, 5, 6, 7]));
+        assert_eq!(fuzzy_match("buy", "BUY MILK"), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn letters_match_in_order_with_gaps() {
+        assert_eq!(fuzzy_match("bmk", "Buy milk"), Some(vec![0, 4, 7]));
+    }
+
+    #[test]
+    fn letters_out_of_order_do_not_match() {
+        assert_eq!(fuzzy_match("klim", "Buy milk"), None);
+        assert_eq!(fuzzy_match("milks", "Buy milk"), None);
+    }
+
+    #[test]
+    fn a_run_of_letters_is_highlighted_together() {
+        // "re" could match the r of "Write" and the e of "report"
+        assert_eq!(fuzzy_match("re", "Write report"), Some(vec![6, 7]));
+    }
+
+    #[test]
+    fn empty_query_matches_with_nothing_highlighted() {
+        assert_eq!(fuzzy_match("", "Buy milk"), Some(Vec::new()));
+        assert_eq!(fuzzy_match("", ""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        assert_eq!(fuzzy_match("ca", "Café au lait"), Some(vec![0, 1]));
+        assert_eq!(fuzzy_match("éa", "Café au lait"), Some(vec![3, 5]));
+    }
+}
@@ -0,0 +1,458 @@
+// ##### Todoist Sync API #####
+
+// These are the functions that keep an
+// in-memory copy of the account up to
+// date. The first sync downloads all
+// of it and every sync after that only
+// asks for what changed since the last
+// sync_token, which the views then read.
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize, Deserializer};
+
+use crate::model::{Project, Section, Task, Due, Label};
+
+/// Sync token that asks Todoist for everything
+pub const FULL_SYNC: &str = "*";
+
+/// Resources the store keeps a copy of
+pub const RESOURCE_TYPES: &str = r#"["projects","sections","items","labels"]"#;
+
+/// One response from the Sync API, holding only what changed
+#[derive(Deserialize, Debug, Default)]
+pub struct SyncResponse {
+    pub sync_token: String,
+    #[serde(default)]
+    pub full_sync: bool,
+    #[serde(default)]
+    pub projects: Vec<SyncProject>,
+    #[serde(default)]
+    pub sections: Vec<SyncSection>,
+    #[serde(default)]
+    pub items: Vec<SyncItem>,
+    #[serde(default)]
+    pub labels: Vec<SyncLabel>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncProject {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub color: i64,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub child_order: i64,
+    #[serde(default, deserialize_with = "flag")]
+    pub shared: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_favorite: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub inbox_project: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub team_inbox: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_deleted: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_archived: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncSection {
+    pub id: i64,
+    pub project_id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub section_order: i64,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_deleted: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_archived: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncItem {
+    pub id: i64,
+    pub project_id: i64,
+    #[serde(default)]
+    pub section_id: Option<i64>,
+    pub content: String,
+    #[serde(default)]
+    pub labels: Vec<i64>,
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub child_order: i64,
+    #[serde(default = "default_priority")]
+    pub priority: i64,
+    #[serde(default)]
+    pub due: Option<SyncDue>,
+    #[serde(default)]
+    pub date_added: String,
+    #[serde(default, deserialize_with = "flag")]
+    pub checked: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_deleted: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncDue {
+    pub date: String,
+    #[serde(default)]
+    pub string: String,
+    #[serde(default)]
+    pub is_recurring: bool,
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncLabel {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub color: i64,
+    #[serde(default)]
+    pub item_order: i64,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_favorite: bool,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_deleted: bool,
+}
+
+impl SyncResponse {
+    /// Whether applying this would change anything in the store
+    pub fn has_changes(&self) -> bool {
+        self.full_sync
+            || !self.projects.is_empty()
+            || !self.sections.is_empty()
+            || !self.items.is_empty()
+            || !self.labels.is_empty()
+    }
+}
+
+impl From<SyncProject> for Project {
+    fn from(p: SyncProject) -> Self {
+        Project {
+            id: p.id,
+            name: p.name,
+            color: p.color,
+            parent_id: p.parent_id,
+            order: p.child_order,
+            comment_count: 0,
+            shared: p.shared,
+            favorite: p.is_favorite,
+            inbox_project: p.inbox_project,
+            team_inbox: p.team_inbox,
+        }
+    }
+}
+
+impl From<SyncSection> for Section {
+    fn from(s: SyncSection) -> Self {
+        Section {
+            id: s.id,
+            project_id: s.project_id,
+            order: s.section_order,
+            name: s.name,
+        }
+    }
+}
+
+impl From<SyncItem> for Task {
+    fn from(i: SyncItem) -> Self {
+        Task {
+            id: i.id,
+            project_id: i.project_id,
+            section_id: i.section_id.unwrap_or(0),
+            content: i.content,
+            completed: i.checked,
+            label_ids: i.labels,
+            parent_id: i.parent_id,
+            order: i.child_order,
+            priority: i.priority,
+            due: i.due.map(Due::from),
+            url: format!("https://todoist.com/showTask?id={}", i.id),
+            comment_count: 0,
+            created: i.date_added,
+        }
+    }
+}
+
+impl From<SyncDue> for Due {
+    /// The Sync API packs a due time into `date`, REST splits it into `datetime`
+    fn from(d: SyncDue) -> Self {
+        let (date, datetime) = match d.date.find('T') {
+            Some(split) => (d.date[..split].to_string(), Some(d.date.clone())),
+            None => (d.date, None),
+        };
+        Due {
+            string: d.string,
+            date,
+            recurring: d.is_recurring,
+            datetime,
+            timezone: d.timezone,
+        }
+    }
+}
+
+impl From<SyncLabel> for Label {
+    fn from(l: SyncLabel) -> Self {
+        Label {
+            id: l.id,
+            name: l.name,
+            color: l.color,
+            order: l.item_order,
+            favorite: l.is_favorite,
+        }
+    }
+}
+
+/// The in-memory copy of the account that views read from
+#[derive(Serialize, Deserialize)]
+pub struct Store {
+    pub sync_token: String,
+    pub projects: HashMap<i64, Project>,
+    pub sections: HashMap<i64, Section>,
+    pub tasks: HashMap<i64, Task>,
+    pub labels: HashMap<i64, Label>,
+}
+
+impl ::std::default::Default for Store {
+    fn default() -> Self {
+        Self {
+            sync_token: FULL_SYNC.to_string(),
+            projects: HashMap::new(),
+            sections: HashMap::new(),
+            tasks: HashMap::new(),
+            labels: HashMap::new(),
+        }
+    }
+}
+
+impl Store {
+    /// Merge a sync response in, dropping anything deleted, archived or completed
+    pub fn apply(&mut self, response: SyncResponse) {
+        if response.full_sync {
+            self.projects.clear();
+            self.sections.clear();
+            self.tasks.clear();
+            self.labels.clear();
+        }
+
+        for project in response.projects {
+            if project.is_deleted || project.is_archived {
+                self.projects.remove(&project.id);
+            } else {
+                self.projects.insert(project.id, project.into());
+            }
+        }
+        for section in response.sections {
+            if section.is_deleted || section.is_archived {
+                self.sections.remove(&section.id);
+            } else {
+                self.sections.insert(section.id, section.into());
+            }
+        }
+        for item in response.items {
+            if item.is_deleted || item.checked {
+                self.tasks.remove(&item.id);
+            } else {
+                self.tasks.insert(item.id, item.into());
+            }
+        }
+        for label in response.labels {
+            if label.is_deleted {
+                self.labels.remove(&label.id);
+            } else {
+                self.labels.insert(label.id, label.into());
+            }
+        }
+
+        self.sync_token = response.sync_token;
+    }
+
+    pub fn projects(&self) -> Vec<Project> {
+        let mut projects: Vec<Project> = self.projects.values().cloned().collect();
+        projects.sort_by_key(|project| project.order);
+        projects
+    }
+
+    pub fn project_sections(&self, project_id: i64) -> Vec<Section> {
+        let mut sections: Vec<Section> = self.sections.values()
+            .filter(|section| section.project_id == project_id)
+            .cloned()
+            .collect();
+        sections.sort_by_key(|section| section.order);
+        sections
+    }
+
+    pub fn tasks(&self) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self.tasks.values().cloned().collect();
+        tasks.sort_by_key(|task| (task.project_id, task.section_id, task.order, task.id));
+        tasks
+    }
+
+    pub fn project_tasks(&self, project_id: i64) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self.tasks.values()
+            .filter(|task| task.project_id == project_id)
+            .cloned()
+            .collect();
+        tasks.sort_by_key(|task| (task.section_id, task.order, task.id));
+        tasks
+    }
+
+    /// The Inbox project, once the first sync has found it
+    pub fn inbox(&self) -> Option<&Project> {
+        self.projects.values().find(|project| project.inbox_project)
+    }
+
+    pub fn labels(&self) -> Vec<Label> {
+        let mut labels: Vec<Label> = self.labels.values().cloned().collect();
+        labels.sort_by_key(|label| label.order);
+        labels
+    }
+}
+
+/// The Sync API sends some flags as `true`/`false` and others as `1`/`0`
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Int(i64),
+    }
+
+    Ok(match Flag::deserialize(deserializer)? {
+        Flag::Bool(value) => value,
+        Flag::Int(value) => value != 0,
+    })
+}
+
+fn default_priority() -> i64 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn response(value: Value) -> SyncResponse {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn item(id: i64, content: &str) -> Value {
+        json!({"id": id, "project_id": 1, "content": content})
+    }
+
+    /// A store after a full sync of one project with two tasks and a label
+    fn synced() -> Store {
+        let mut store = Store::default();
+        store.apply(response(json!({
+            "sync_token": "first",
+            "full_sync": true,
+            "projects": [{"id": 1, "name": "Inbox", "inbox_project": true}],
+            "items": [item(10, "Buy milk"), item(11, "Call mum")],
+            "labels": [{"id": 7, "name": "waiting"}],
+        })));
+        store
+    }
+
+    fn contents(store: &Store) -> Vec<String> {
+        store.tasks().into_iter().map(|task| task.content).collect()
+    }
+
+    #[test]
+    fn full_sync_replaces_everything() {
+        let mut store = synced();
+        store.apply(response(json!({
+            "sync_token": "second",
+            "full_sync": true,
+            "projects": [{"id": 2, "name": "Work"}],
+            "items": [{"id": 20, "project_id": 2, "content": "Write report"}],
+        })));
+
+        assert_eq!(store.sync_token, "second");
+        assert_eq!(store.projects().iter().map(|p| p.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(contents(&store), vec!["Write report"]);
+        assert!(store.labels().is_empty());
+    }
+
+    #[test]
+    fn incremental_sync_updates_and_adds_in_place() {
+        let mut store = synced();
+        store.apply(response(json!({
+            "sync_token": "second",
+            "items": [item(11, "Call mum back"), item(12, "Pay rent")],
+        })));
+
+        assert_eq!(store.sync_token, "second");
+        assert_eq!(contents(&store), vec!["Buy milk", "Call mum back", "Pay rent"]);
+        assert!(store.inbox().is_some());
+        assert_eq!(store.labels().len(), 1);
+    }
+
+    #[test]
+    fn deleted_archived_and_completed_are_dropped() {
+        let mut store = synced();
+        store.apply(response(json!({
+            "sync_token": "second",
+            "projects": [{"id": 1, "name": "Inbox", "is_archived": 1}],
+            "items": [
+                {"id": 10, "project_id": 1, "content": "Buy milk", "is_deleted": 1},
+                {"id": 11, "project_id": 1, "content": "Call mum", "checked": true},
+            ],
+            "labels": [{"id": 7, "name": "waiting", "is_deleted": true}],
+        })));
+
+        assert!(store.projects().is_empty());
+        assert!(store.tasks().is_empty());
+        assert!(store.labels().is_empty());
+    }
+
+    #[test]
+    fn nothing_new_is_no_change() {
+        assert!(!response(json!({"sync_token": "second"})).has_changes());
+        assert!(response(json!({"sync_token": "second", "items": [item(10, "Buy milk")]})).has_changes());
+    }
+
+    #[test]
+    fn due_date_alone_has_no_time() {
+        let due = Due::from(SyncDue {
+            date: "2026-10-20".to_string(),
+            string: "tue".to_string(),
+            is_recurring: false,
+            timezone: None,
+        });
+
+        assert_eq!(due.date, "2026-10-20");
+        assert_eq!(due.datetime, None);
+        assert_eq!(due.string, "tue");
+    }
+
+    #[test]
+    fn due_time_is_split_off_the_date() {
+        let floating = Due::from(SyncDue {
+            date: "2026-10-20T17:00:00".to_string(),
+            string: "tue 5pm".to_string(),
+            is_recurring: true,
+            timezone: None,
+        });
+        assert_eq!(floating.date, "2026-10-20");
+        assert_eq!(floating.datetime.as_deref(), Some("2026-10-20T17:00:00"));
+        assert!(floating.recurring);
+
+        let fixed = Due::from(SyncDue {
+            date: "2026-10-20T15:00:00Z".to_string(),
+            string: "tue 5pm".to_string(),
+            is_recurring: false,
+            timezone: Some("Europe/Berlin".to_string()),
+        });
+        assert_eq!(fixed.date, "2026-10-20");
+        assert_eq!(fixed.datetime.as_deref(), Some("2026-10-20T15:00:00Z"));
+        assert_eq!(fixed.timezone.as_deref(), Some("Europe/Berlin"));
+    }
+}
@@ -0,0 +1,90 @@
+// ##### Test Support #####
+
+// These are the builders the unit tests
+// share for the models they run on. Each
+// starts from a plain value and the rest
+// change one field at a time, so a test
+// only spells out what it cares about.
+
+use chrono::NaiveDate;
+
+use crate::model::{Due, Label, Project, Task};
+
+pub fn project(id: i64, name: &str, parent_id: Option<i64>) -> Project {
+    Project {
+        id,
+        name: name.to_string(),
+        color: 0,
+        parent_id,
+        order: 0,
+        comment_count: 0,
+        shared: false,
+        favorite: false,
+        inbox_project: false,
+        team_inbox: false,
+    }
+}
+
+pub fn label(id: i64, name: &str) -> Label {
+    Label { id, name: name.to_string(), color: 0, order: 0, favorite: false }
+}
+
+/// An open task with normal priority, no section, labels or due date
+pub fn task(id: i64, project_id: i64, content: &str) -> Task {
+    Task {
+        id,
+        project_id,
+        section_id: 0,
+        content: content.to_string(),
+        completed: false,
+        label_ids: Vec::new(),
+        parent_id: None,
+        order: 0,
+        priority: 1,
+        due: None,
+        url: String::new(),
+        comment_count: 0,
+        created: String::new(),
+    }
+}
+
+/// Due on `date` (YYYY-MM-DD), with no time of day
+pub fn due(mut task: Task, date: &str) -> Task {
+    task.due = Some(Due {
+        string: date.to_string(),
+        date: date.to_string(),
+        recurring: false,
+        datetime: None,
+        timezone: None,
+    });
+    task
+}
+
+/// Due on `date` at `datetime`, written however the API under test writes it
+pub fn due_at(task: Task, date: &str, datetime: &str) -> Task {
+    let mut task = due(task, date);
+    if let Some(due) = task.due.as_mut() {
+        due.datetime = Some(datetime.to_string());
+    }
+    task
+}
+
+pub fn priority(mut task: Task, priority: i64) -> Task {
+    task.priority = priority;
+    task
+}
+
+pub fn labelled(mut task: Task, label_ids: &[i64]) -> Task {
+    task.label_ids = label_ids.to_vec();
+    task
+}
+
+pub fn section(mut task: Task, section_id: i64) -> Task {
+    task.section_id = section_id;
+    task
+}
+
+/// A date written YYYY-MM-DD
+pub fn date(text: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+}
@@ -0,0 +1,371 @@
+// ##### Themes #####
+
+// These are the color themes, either
+// bundled into the binary from styles/
+// or written by the user as *.toml in
+// the themes folder of the config dir.
+// A user theme can inherit a bundled
+// one and override just a few colors.
+// Every theme can be checked for bad
+// colors and for text that is hard to
+// read against what it is drawn on.
+
+use std::fs;
+use std::path::PathBuf;
+
+use cursive::Cursive;
+use cursive::theme::{BaseColor, Color, PaletteColor, Theme};
+use toml::Value;
+
+use crate::config;
+use crate::error::CotoError;
+
+/// The theme used when none is configured, or the configured one is missing
+pub const DEFAULT_THEME: &str = "original";
+
+/// Folder in the config directory that user themes are read from
+pub const THEME_DIR: &str = "themes";
+
+/// Every bundled theme, by name
+pub const THEMES: &[(&str, &str)] = &[
+    ("amethyst", include_str!("../styles/amethyst.toml")),
+    ("blueberry", include_str!("../styles/blueberry.toml")),
+    ("clover", include_str!("../styles/clover.toml")),
+    ("dark", include_str!("../styles/dark.toml")),
+    ("graphite", include_str!("../styles/graphite.toml")),
+    ("neutral", include_str!("../styles/neutral.toml")),
+    ("noir", include_str!("../styles/noir.toml")),
+    ("original", include_str!("../styles/original.toml")),
+    ("sky", include_str!("../styles/sky.toml")),
+    ("sunflower", include_str!("../styles/sunflower.toml")),
+    ("tangerine", include_str!("../styles/tangerine.toml")),
+];
+
+/// The keys a theme may set under `[colors]`
+pub const COLOR_KEYS: &[&str] = &[
+    "background", "shadow", "view", "primary", "secondary", "tertiary",
+    "title_primary", "title_secondary", "highlight", "highlight_inactive", "highlight_text",
+];
+
+/// Text and the color it is drawn on. Titles are checked on the background
+/// as well as on the view color, which is where cursive draws dialog titles.
+/// Selections use highlight_text on the highlight.
+const CONTRAST_PAIRS: &[(&str, PaletteColor, &str, PaletteColor)] = &[
+    ("primary", PaletteColor::Primary, "view", PaletteColor::View),
+    ("title_primary", PaletteColor::TitlePrimary, "background", PaletteColor::Background),
+    ("title_secondary", PaletteColor::TitleSecondary, "background", PaletteColor::Background),
+    ("title_primary", PaletteColor::TitlePrimary, "view", PaletteColor::View),
+    ("highlight_text", PaletteColor::HighlightText, "highlight", PaletteColor::Highlight),
+    ("highlight_text", PaletteColor::HighlightText, "highlight_inactive", PaletteColor::HighlightInactive),
+];
+
+/// WCAG AA's minimum contrast ratio for normal sized text
+pub const AA_CONTRAST: f64 = 4.5;
+
+/// What checking a theme found. Errors stop a theme loading, warnings don't.
+#[derive(Default)]
+pub struct ThemeCheck {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+fn bundled(name: &str) -> Option<&'static str> {
+    THEMES.iter().find(|(theme, _)| *theme == name).map(|(_, toml)| *toml)
+}
+
+fn user_dir() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(THEME_DIR))
+}
+
+fn user_path(name: &str) -> Option<PathBuf> {
+    user_dir()
+        .map(|dir| dir.join(format!("{}.toml", name)))
+        .filter(|path| path.is_file())
+}
+
+/// The bundled themes followed by the user's own, sorted by name
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = THEMES.iter().map(|(name, _)| name.to_string()).collect();
+
+    let mut user_names: Vec<String> = user_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .filter(|name| !names.contains(name))
+        .collect();
+    user_names.sort();
+
+    names.append(&mut user_names);
+    names
+}
+
+/// Whether `name` is read from the user's themes folder
+pub fn is_user_theme(name: &str) -> bool {
+    user_path(name).is_some()
+}
+
+/// Load a theme by name, preferring a user theme over a bundled one.
+/// Bundled themes are validated the same way, so neither loads with a bad color.
+pub fn load(name: &str) -> Result<Theme, CotoError> {
+    let (theme, check) = read(name, &source(name)?)?;
+    match check.errors.first() {
+        Some(error) => Err(theme_error(name, error.to_string())),
+        None => Ok(theme),
+    }
+}
+
+/// Switch to a theme, leaving the current one in place if it does not
+/// load. Hands back any contrast warnings.
+pub fn apply(ui: &mut Cursive, name: &str) -> Result<Vec<String>, CotoError> {
+    let theme = load(name)?;
+    let warnings = contrast_warnings(&theme);
+    ui.set_theme(theme);
+    Ok(warnings)
+}
+
+/// Validate every setting and color of a theme, bundled or not, and
+/// measure the contrast of each pair of colors text is drawn in
+pub fn check(name: &str) -> Result<ThemeCheck, CotoError> {
+    let source = source(name)?;
+    let (theme, mut check) = read(name, &source)?;
+    if source.trim().is_empty() {
+        check.warnings.push("sets nothing, so cursive's default colors are used".to_string());
+    }
+    check.warnings.append(&mut contrast_warnings(&theme));
+    Ok(check)
+}
+
+/// A theme's toml, from the user's themes folder or else bundled
+fn source(name: &str) -> Result<String, CotoError> {
+    match user_path(name) {
+        Some(path) => Ok(fs::read_to_string(path)?),
+        None => bundled(name).map(str::to_string).ok_or_else(|| no_such_theme(name)),
+    }
+}
+
+/// Read a theme's settings over the bundled theme it inherits, noting every
+/// bad key or color. Only toml that cannot be read at all is an Err.
+fn read(name: &str, source: &str) -> Result<(Theme, ThemeCheck), CotoError> {
+    let table = match source.parse::<Value>() {
+        Ok(Value::Table(table)) => table,
+        Ok(_) => return Err(theme_error(name, "expected a table of settings".to_string())),
+        Err(e) => return Err(theme_error(name, e.to_string())),
+    };
+
+    let mut check = ThemeCheck::default();
+    let mut theme = match table.get("inherits") {
+        None => Theme::default(),
+        Some(Value::String(parent)) => match bundled(parent) {
+            Some(toml) => cursive::theme::load_toml(toml).expect("Bundled themes are valid toml"),
+            None => return Err(theme_error(name, format!("inherits `{}`, which is not a bundled theme", parent))),
+        },
+        Some(other) => return Err(theme_error(name, format!("inherits should be a theme name, not {}", other))),
+    };
+
+    for (key, value) in table.iter() {
+        match (key.as_str(), value) {
+            ("inherits", _) => {}
+            ("shadow", Value::Boolean(shadow)) => theme.shadow = *shadow,
+            ("borders", Value::String(borders)) if ["simple", "outset", "none"].contains(&borders.as_str()) => {
+                theme.borders = borders.into();
+            }
+            ("borders", other) => {
+                check.errors.push(format!("borders should be \"simple\", \"outset\" or \"none\", not {}", other));
+            }
+            ("colors", Value::Table(colors)) => {
+                for (key, value) in colors.iter() {
+                    if !COLOR_KEYS.contains(&key.as_str()) {
+                        check.errors.push(format!("colors.{} is not a theme color (use one of {})",
+                            key, COLOR_KEYS.join(", ")));
+                        continue;
+                    }
+                    let color = match value {
+                        Value::String(color) => parse_color(color),
+                        other => Err(format!("{} is not a color string", other)),
+                    };
+                    match color {
+                        Ok(color) => theme.palette.set_color(key, color),
+                        Err(message) => check.errors.push(format!("colors.{}: {}", key, message)),
+                    }
+                }
+            }
+            (key, other) => check.errors.push(format!("unexpected setting {} = {}", key, other)),
+        }
+    }
+
+    Ok((theme, check))
+}
+
+/// A color as cursive understands it, but without silently reading bad hex
+/// digits as zero or guessing that six bare characters are hex
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let hex = value.strip_prefix('#').or_else(|| value.strip_prefix("0x"));
+
+    match hex {
+        Some(digits) if (digits.len() == 6 || digits.len() == 3) && digits.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Color::parse(value).ok_or_else(|| format!("`{}` is not a color", value))
+        }
+        Some(_) => Err(format!("`{}` is not a #rgb or #rrggbb color", value)),
+        None if value.len() == 6 && value.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Err(format!("`{}` is missing the leading `#`", value))
+        }
+        None => Color::parse(value).ok_or_else(|| format!("`{}` is not a color name or #rrggbb value", value)),
+    }
+}
+
+/// Every text/background pair below WCAG AA. Pairs using the terminal's
+/// default color are skipped, since only the terminal knows what it is.
+pub fn contrast_warnings(theme: &Theme) -> Vec<String> {
+    CONTRAST_PAIRS.iter()
+        .filter_map(|(front_name, front, back_name, back)| {
+            let front = rgb(theme.palette[*front])?;
+            let back = rgb(theme.palette[*back])?;
+            let ratio = contrast_ratio(front, back);
+            if ratio < AA_CONTRAST {
+                Some(format!("{} on {}: contrast {:.2}:1 is below the WCAG AA minimum of {}:1",
+                    front_name, back_name, ratio, AA_CONTRAST))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The WCAG contrast ratio between two colors, from 1 to 21
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    let (light, dark) = if a > b { (a, b) } else { (b, a) };
+    (light + 0.05) / (dark + 0.05)
+}
+
+/// WCAG relative luminance of an sRGB color
+fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.039_28 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// A palette color as RGB, using xterm's values for the named terminal colors
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::RgbLowRes(r, g, b) => Some((r * 51, g * 51, b * 51)),
+        Color::Dark(base) => Some(match base {
+            BaseColor::Black => (0, 0, 0),
+            BaseColor::Red => (205, 0, 0),
+            BaseColor::Green => (0, 205, 0),
+            BaseColor::Yellow => (205, 205, 0),
+            BaseColor::Blue => (0, 0, 238),
+            BaseColor::Magenta => (205, 0, 205),
+            BaseColor::Cyan => (0, 205, 205),
+            BaseColor::White => (229, 229, 229),
+        }),
+        Color::Light(base) => Some(match base {
+            BaseColor::Black => (127, 127, 127),
+            BaseColor::Red => (255, 0, 0),
+            BaseColor::Green => (0, 255, 0),
+            BaseColor::Yellow => (255, 255, 0),
+            BaseColor::Blue => (92, 92, 255),
+            BaseColor::Magenta => (255, 0, 255),
+            BaseColor::Cyan => (0, 255, 255),
+            BaseColor::White => (255, 255, 255),
+        }),
+        Color::TerminalDefault => None,
+    }
+}
+
+fn no_such_theme(name: &str) -> CotoError {
+    theme_error(name, "there is no bundled or user theme with that name".to_string())
+}
+
+fn theme_error(name: &str, message: String) -> CotoError {
+    CotoError::Theme { name: name.to_string(), message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled_theme(name: &str) -> Theme {
+        cursive::theme::load_toml(bundled(name).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn bundled_themes_have_no_errors() {
+        for (name, toml) in THEMES.iter() {
+            let (_, check) = read(name, toml).unwrap();
+            assert!(check.errors.is_empty(), "{}: {:?}", name, check.errors);
+        }
+    }
+
+    #[test]
+    fn hex_colors_must_be_whole() {
+        assert_eq!(parse_color("#ff8800"), Ok(Color::Rgb(255, 136, 0)));
+        assert!(parse_color("#fff").is_ok());
+        assert!(parse_color("red").is_ok());
+
+        assert!(parse_color("#ff88zz").unwrap_err().contains("not a #rgb or #rrggbb color"));
+        assert!(parse_color("#ff88").is_err());
+        assert!(parse_color("ff8800").unwrap_err().contains("missing the leading `#`"));
+        assert!(parse_color("reddish").is_err());
+    }
+
+    #[test]
+    fn contrast_runs_from_one_to_twenty_one() {
+        let black = (0, 0, 0);
+        let white = (255, 255, 255);
+
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 1e-9);
+        assert!((contrast_ratio(white, black) - 21.0).abs() < 1e-9);
+        assert!((contrast_ratio((119, 119, 119), (119, 119, 119)) - 1.0).abs() < 1e-9);
+        // #767676 is the lightest grey that passes AA on white
+        assert!(contrast_ratio((118, 118, 118), white) >= AA_CONTRAST);
+        assert!(contrast_ratio((119, 119, 119), white) < AA_CONTRAST);
+    }
+
+    #[test]
+    fn low_contrast_is_a_warning() {
+        let (theme, check) = read("faint", "[colors]\nview = \"#ffffff\"\nprimary = \"#eeeeee\"").unwrap();
+
+        assert!(check.errors.is_empty());
+        let warnings = contrast_warnings(&theme);
+        assert!(warnings.iter().any(|warning| warning.starts_with("primary on view")));
+    }
+
+    #[test]
+    fn inherited_colors_are_kept_unless_overridden() {
+        let (theme, check) = read("mine", "inherits = \"noir\"\n[colors]\nprimary = \"#ff0000\"").unwrap();
+        let noir = bundled_theme("noir");
+
+        assert!(check.errors.is_empty());
+        assert_eq!(theme.palette[PaletteColor::Primary], Color::Rgb(255, 0, 0));
+        assert_eq!(theme.palette[PaletteColor::View], noir.palette[PaletteColor::View]);
+        assert_eq!(theme.borders, noir.borders);
+    }
+
+    #[test]
+    fn only_bundled_themes_can_be_inherited() {
+        // Not even a theme inheriting itself, so there is no loop to follow
+        assert!(read("loop", "inherits = \"loop\"").is_err());
+        assert!(read("mine", "inherits = \"missing\"").is_err());
+        assert!(read("mine", "inherits = 3").is_err());
+    }
+
+    #[test]
+    fn bad_settings_are_errors_not_failures() {
+        let (_, check) = read("mine", "borders = \"double\"\nsize = 3\n[colors]\nprimary = \"#12\"\ntext = \"red\"").unwrap();
+        assert_eq!(check.errors.len(), 4);
+
+        assert!(read("mine", "[colors").is_err());
+    }
+}
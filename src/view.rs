@@ -0,0 +1,281 @@
+// ##### Saved Views #####
+
+// These are the views kept in the config,
+// each a filter query with a sort order,
+// a grouping and a layout, along with the
+// functions that put the tasks a filter
+// found into the shape its view asks for.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+
+use crate::agenda;
+use crate::error::CotoError;
+use crate::filter;
+use crate::model::{Project, Label, Task};
+use crate::sync::Store;
+
+/// A named view, saved in the config under `[[views]]`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SavedView {
+    pub name: String,
+    /// Todoist filter query picking the tasks, every task when left empty
+    pub filter: String,
+    pub sort: Sort,
+    pub group: Group,
+    pub layout: Layout,
+}
+
+impl ::std::default::Default for SavedView {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            filter: String::new(),
+            sort: Sort::Order,
+            group: Group::None,
+            layout: Layout::List,
+        }
+    }
+}
+
+/// How tasks are ordered within a group
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// The order Todoist keeps them in
+    Order,
+    /// Soonest due first, undated tasks last
+    Due,
+    /// Most urgent first
+    Priority,
+    /// Alphabetically by content
+    Content,
+}
+
+/// What tasks are grouped under
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Group {
+    None,
+    Project,
+    Priority,
+    Due,
+    /// A task with several labels shows up under each of them
+    Label,
+}
+
+/// How the TUI lays the groups out
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Groups one under the other
+    List,
+    /// Groups side by side as columns
+    Board,
+}
+
+impl SavedView {
+    /// A filter query typed in on the spot, shown as it comes
+    pub fn query(filter: &str) -> Self {
+        Self {
+            name: filter.to_string(),
+            filter: filter.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// The filter to send Todoist, if the view has one
+    pub fn query_filter(&self) -> Option<&str> {
+        Some(self.filter.trim()).filter(|filter| !filter.is_empty())
+    }
+}
+
+/// The tasks in `store` a view picks, for when Todoist can't run its filter
+pub fn run_locally(view: &SavedView, store: &Store, today: NaiveDate) -> Result<Vec<Task>, CotoError> {
+    match view.query_filter() {
+        Some(query) => filter::run(query, store, today),
+        None => Ok(store.tasks()),
+    }
+}
+
+/// Sort `tasks` and split them into headed groups as `view` asks. Empty
+/// groups are left out, and a view without grouping has one unnamed group.
+pub fn arrange(view: &SavedView, mut tasks: Vec<Task>, projects: &[Project], labels: &[Label],
+    today: NaiveDate) -> Vec<(String, Vec<Task>)> {
+    match view.sort {
+        Sort::Order => {}
+        Sort::Due => tasks.sort_by(|a, b| {
+            let time = |task: &Task| task.due.as_ref().and_then(|due| due.datetime.clone());
+            let date = |task: &Task| agenda::due_date(task);
+            (date(a).is_none(), date(a), time(a), Reverse(a.priority))
+                .cmp(&(date(b).is_none(), date(b), time(b), Reverse(b.priority)))
+        }),
+        Sort::Priority => tasks.sort_by_key(|task| Reverse(task.priority)),
+        Sort::Content => tasks.sort_by_key(|task| task.content.to_lowercase()),
+    }
+
+    match view.group {
+        Group::None if tasks.is_empty() => Vec::new(),
+        Group::None => vec![(String::new(), tasks)],
+        // Tasks in a project that isn't listed come last, like undated and unlabelled ones
+        Group::Project => bucket(tasks,
+            |task| {
+                let position = projects.iter().position(|project| project.id == task.project_id);
+                (position.unwrap_or(projects.len()), task.project_id)
+            },
+            |(position, _)| projects.get(*position).map_or_else(|| "Other".to_string(), |project| project.name.to_string())),
+        Group::Priority => bucket(tasks,
+            |task| Reverse(task.priority),
+            |Reverse(priority)| format!("p{}", 5 - priority)),
+        Group::Due => bucket(tasks,
+            |task| match agenda::due_date(task) {
+                Some(date) if date < today => (0, None),
+                Some(date) => (1, Some(date)),
+                None => (2, None),
+            },
+            |key| match key {
+                (0, _) => "Overdue".to_string(),
+                (_, Some(date)) => date.format("%A %-d %B").to_string(),
+                _ => "No date".to_string(),
+            }),
+        Group::Label => {
+            let mut groups: Vec<(String, Vec<Task>)> = labels.iter()
+                .map(|label| {
+                    let tasks = tasks.iter().filter(|task| task.label_ids.contains(&label.id)).cloned().collect();
+                    (format!("@{}", label.name), tasks)
+                })
+                .collect();
+            let unlabelled = tasks.into_iter()
+                .filter(|task| !labels.iter().any(|label| task.label_ids.contains(&label.id)))
+                .collect();
+            groups.push(("No label".to_string(), unlabelled));
+            groups.into_iter().filter(|(_, tasks)| !tasks.is_empty()).collect()
+        }
+    }
+}
+
+/// Group tasks by `key`, in key order, keeping their order within a group
+fn bucket<K: Ord>(tasks: Vec<Task>, key: impl Fn(&Task) -> K, heading: impl Fn(&K) -> String)
+    -> Vec<(String, Vec<Task>)> {
+    let mut groups: BTreeMap<K, Vec<Task>> = BTreeMap::new();
+    for task in tasks.into_iter() {
+        groups.entry(key(&task)).or_default().push(task);
+    }
+    groups.into_iter()
+        .map(|(key, tasks)| (heading(&key), tasks))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{date, due, due_at, label, labelled, priority, project, task};
+
+    fn view(sort: Sort, group: Group) -> SavedView {
+        SavedView { sort, group, ..SavedView::default() }
+    }
+
+    fn projects() -> Vec<Project> {
+        vec![project(2, "Work", None), project(1, "Inbox", None)]
+    }
+
+    fn labels() -> Vec<Label> {
+        vec![label(7, "home"), label(8, "phone")]
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            priority(due(task(1, 1, "pay rent"), "2026-10-20"), 2),
+            labelled(task(2, 2, "Call the bank"), &[8]),
+            priority(labelled(due(task(3, 1, "Water plants"), "2026-10-15"), &[7, 8]), 4),
+            due_at(task(4, 2, "Standup"), "2026-10-18", "2026-10-18T09:30:00"),
+            due(task(5, 9, "Archive mail"), "2026-10-18"),
+        ]
+    }
+
+    fn arranged(sort: Sort, group: Group) -> Vec<(String, Vec<i64>)> {
+        arrange(&view(sort, group), tasks(), &projects(), &labels(), date("2026-10-18"))
+            .into_iter()
+            .map(|(heading, tasks)| (heading, tasks.iter().map(|task| task.id).collect()))
+            .collect()
+    }
+
+    fn flat(sort: Sort) -> Vec<i64> {
+        arranged(sort, Group::None).remove(0).1
+    }
+
+    #[test]
+    fn order_keeps_todoists_order() {
+        assert_eq!(flat(Sort::Order), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn due_puts_undated_last_and_times_after_the_day() {
+        assert_eq!(flat(Sort::Due), vec![3, 5, 4, 1, 2]);
+    }
+
+    #[test]
+    fn priority_is_most_urgent_first_and_otherwise_stable() {
+        assert_eq!(flat(Sort::Priority), vec![3, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn content_ignores_case() {
+        assert_eq!(flat(Sort::Content), vec![5, 2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn no_grouping_is_one_unnamed_group_unless_empty() {
+        assert_eq!(arranged(Sort::Order, Group::None), vec![(String::new(), vec![1, 2, 3, 4, 5])]);
+        assert!(arrange(&view(Sort::Order, Group::None), Vec::new(), &[], &[], date("2026-10-18")).is_empty());
+    }
+
+    #[test]
+    fn projects_group_in_project_order_with_unknown_last() {
+        assert_eq!(arranged(Sort::Order, Group::Project), vec![
+            ("Work".to_string(), vec![2, 4]),
+            ("Inbox".to_string(), vec![1, 3]),
+            ("Other".to_string(), vec![5]),
+        ]);
+    }
+
+    #[test]
+    fn priorities_group_most_urgent_first() {
+        assert_eq!(arranged(Sort::Content, Group::Priority), vec![
+            ("p1".to_string(), vec![3]),
+            ("p3".to_string(), vec![1]),
+            ("p4".to_string(), vec![5, 2, 4]),
+        ]);
+    }
+
+    #[test]
+    fn due_groups_overdue_then_by_day_then_undated() {
+        assert_eq!(arranged(Sort::Order, Group::Due), vec![
+            ("Overdue".to_string(), vec![3]),
+            ("Sunday 18 October".to_string(), vec![4, 5]),
+            ("Tuesday 20 October".to_string(), vec![1]),
+            ("No date".to_string(), vec![2]),
+        ]);
+    }
+
+    #[test]
+    fn labels_group_a_task_under_each_of_its_labels() {
+        assert_eq!(arranged(Sort::Order, Group::Label), vec![
+            ("@home".to_string(), vec![3]),
+            ("@phone".to_string(), vec![2, 3]),
+            ("No label".to_string(), vec![1, 4, 5]),
+        ]);
+    }
+
+    #[test]
+    fn empty_label_groups_are_left_out() {
+        let tasks = vec![labelled(task(1, 1, "Call mum"), &[8])];
+        let groups = arrange(&view(Sort::Order, Group::Label), tasks, &projects(), &labels(), date("2026-10-18"));
+
+        assert_eq!(groups.iter().map(|(heading, _)| heading.as_str()).collect::<Vec<_>>(), vec!["@phone"]);
+    }
+}